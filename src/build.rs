@@ -41,6 +41,10 @@ fn compiler(config_dir: &Path, vendor: &Path, target_arch: &str, target_feature:
         c.flag_if_supported("-fexceptions");
     }
 
+    if env::var("TARGET").is_ok_and(|t| t == "wasm32-unknown-unknown") {
+        c.include(vendor.with_file_name("wasm-libc").join("include"));
+    }
+
     (c, simd_abi)
 }
 
@@ -57,10 +61,10 @@ fn main() {
 
     println!("cargo:rerun-if-changed={}", vendor.display());
 
-    // cc crate needs emscripten target to use correct `ar`
-    if env::var("TARGET").map_or(false, |t| t == "wasm32-unknown-unknown") {
-        println!("cargo:warning=If the build fails, try using wasm32-unknown-emscripten target instead");
-        eprintln!("If the build fails, try using wasm32-unknown-emscripten target instead");
+    // This target has no libc, so a minimal one is provided (and memory is allocated by Rust)
+    let no_libc = env::var("TARGET").is_ok_and(|t| t == "wasm32-unknown-unknown");
+    if no_libc {
+        println!("cargo:rerun-if-changed={}", root.join("wasm-libc").display());
     }
 
     if cfg!(feature = "unwinding") && env::var_os("CARGO_CFG_PANIC").as_deref() == Some("abort".as_ref()) {
@@ -85,7 +89,7 @@ fn main() {
         "vendor/jdmaster.c", "vendor/jdmerge.c", "vendor/jdphuff.c", "vendor/jdpostct.c",
         "vendor/jdsample.c", "vendor/jdtrans.c", "vendor/jerror.c", "vendor/jfdctflt.c",
         "vendor/jfdctfst.c", "vendor/jfdctint.c", "vendor/jidctflt.c", "vendor/jidctfst.c",
-        "vendor/jidctint.c", "vendor/jidctred.c", "vendor/jmemmgr.c", "vendor/jmemnobs.c",
+        "vendor/jidctint.c", "vendor/jidctred.c", "vendor/jmemmgr.c",
         "vendor/jquant1.c", "vendor/jquant2.c", "vendor/jutils.c",
     ];
 
@@ -94,6 +98,14 @@ fn main() {
         c.file(file);
     }
 
+    // `rust_alloc` implements it in Rust
+    if !cfg!(feature = "rust_alloc") && !no_libc {
+        c.file("vendor/jmemnobs.c");
+    }
+    if no_libc {
+        c.file("wasm-libc/libc.c");
+    }
+
     if cfg!(feature = "icc_io") {
         c.file("vendor/jcicc.c");
         c.file("vendor/jdicc.c");
@@ -189,6 +201,22 @@ fn main() {
         "#
     ).expect("write");
 
+    // Everything the C code exports gets renamed, so that it doesn't clash with another libjpeg in the same process.
+    // `src/lib.rs` links to the prefixed names, and C code using `DEP_JPEG_INCLUDE` gets them from this header.
+    if cfg!(feature = "prefix_symbols") {
+        let mut sources = files.to_vec();
+        sources.extend([
+            "vendor/jmemnobs.c", "vendor/jcicc.c", "vendor/jdicc.c", "vendor/jcarith.c", "vendor/jdarith.c",
+            "vendor/jaricom.c", "vendor/transupp.c", "vendor/turbojpeg.c", "vendor/jdatadst-tj.c",
+            "vendor/jdatasrc-tj.c", "vendor/jsimd_none.c", "vendor/simd/x86_64/jsimd.c", "vendor/simd/i386/jsimd.c",
+            "vendor/simd/mips/jsimd.c", "vendor/simd/powerpc/jsimd.c", "vendor/simd/arm/aarch32/jsimd.c",
+            "vendor/simd/arm/aarch64/jsimd.c",
+        ]);
+        for name in library_symbols(&vendor, &sources) {
+            writeln!(jconfig_h, "#define {name} mozjpeg_{name}").expect("write");
+        }
+    }
+
     let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
     if target_os == "ios" && env::var_os("IPHONEOS_DEPLOYMENT_TARGET").is_none() {
         // thread-local storage is not supported on iOS 9
@@ -221,7 +249,8 @@ fn main() {
     let nasm_needed_for_arch = target_arch == "x86_64" || target_arch == "x86";
 
     let with_simd = cfg!(feature = "with_simd")
-        && target_arch != "wasm32" // no WASM-SIMD support here
+        && target_arch != "wasm32" // WASM-SIMD is in Rust, see `wasm_simd`
+        && asm_simd_supported(&target_arch)
         && if nasm_needed_for_arch { nasm_supported() } else { gas_supported(&c) };
 
     #[cfg(feature = "with_simd")]
@@ -270,26 +299,26 @@ fn main() {
 
             match target_arch.as_str() {
                 "x86_64" => {
-                    c.file("vendor/simd/x86_64/jsimd.c");
+                    c.file(jsimd_c(&config_dir, &vendor, "x86_64/jsimd.c"));
                 },
                 "x86" => {
                     c.flag_if_supported("-msse2");
-                    c.file("vendor/simd/i386/jsimd.c");
+                    c.file(jsimd_c(&config_dir, &vendor, "i386/jsimd.c"));
                 },
-                "mips" => {c.file("vendor/simd/mips/jsimd.c");},
+                "mips" => {c.file(jsimd_c(&config_dir, &vendor, "mips/jsimd.c"));},
                 "powerpc" | "powerpc64" => {
                     c.flag_if_supported("-maltivec");
-                    c.file("vendor/simd/powerpc/jsimd.c");
+                    c.file(jsimd_c(&config_dir, &vendor, "powerpc/jsimd.c"));
                 },
                 "arm" => {
                     c.file("vendor/simd/arm/aarch32/jchuff-neon.c");
                     c.file("vendor/simd/arm/jdcolor-neon.c");
                     c.file("vendor/simd/arm/jfdctint-neon.c");
-                    c.file("vendor/simd/arm/aarch32/jsimd.c");
+                    c.file(jsimd_c(&config_dir, &vendor, "arm/aarch32/jsimd.c"));
                 },
                 "aarch64" => {
                     c.file("vendor/simd/arm/jidctfst-neon.c");
-                    c.file("vendor/simd/arm/aarch64/jsimd.c");
+                    c.file(jsimd_c(&config_dir, &vendor, "arm/aarch64/jsimd.c"));
                 },
                 _ => {},
             }
@@ -307,13 +336,324 @@ fn main() {
             }
         }
     }
+    // `src/jsimd_wasm.rs` replaces `jsimd_none.c`
+    let wasm_simd = cfg!(feature = "wasm_simd") && target_arch == "wasm32";
+    if wasm_simd {
+        jconfig_h.write_all(b"#define WITH_SIMD 1\n").unwrap();
+        c.flag("-msimd128");
+    }
+    // `src/jsimd_x86.rs` replaces the nasm code
+    let x86_intrinsics_simd = cfg!(feature = "x86_intrinsics_simd") && target_arch == "x86_64" && !with_simd;
+    // `src/jsimd_portable.rs` is the fallback when there's no assembly for the target
+    let portable_simd = cfg!(feature = "portable_simd") && !with_simd && !wasm_simd && !x86_intrinsics_simd;
+    let rust_simd = wasm_simd || x86_intrinsics_simd || portable_simd;
+    if x86_intrinsics_simd || portable_simd {
+        jconfig_h.write_all(b"#define WITH_SIMD 1\n").unwrap();
+    }
     drop(jconfig_h); // close the file
 
-    if !with_simd {
+    // `WITH_SIMD` changes `DCTELEM`, and `simd_info()` needs to know whether `jpeg_simd_cpu_support` exists
+    for cfg in ["mozjpeg_simd", "mozjpeg_rust_simd", "mozjpeg_x86_intrinsics_simd", "mozjpeg_portable_simd"] {
+        println!("cargo:rustc-check-cfg=cfg({cfg})");
+    }
+    if with_simd || rust_simd {
+        println!("cargo:rustc-cfg=mozjpeg_simd");
+    }
+    if rust_simd {
+        println!("cargo:rustc-cfg=mozjpeg_rust_simd");
+    }
+    if x86_intrinsics_simd {
+        println!("cargo:rustc-cfg=mozjpeg_x86_intrinsics_simd");
+    }
+    if portable_simd {
+        println!("cargo:rustc-cfg=mozjpeg_portable_simd");
+    }
+
+    if !with_simd && !rust_simd {
         c.file("vendor/jsimd_none.c");
     }
 
     c.compile(&format!("mozjpeg{abi}{simd_abi}"));
+
+    // Only the test in `src/layout.rs` uses it, so it's a separate archive, and any failure only skips the test
+    println!("cargo:rustc-check-cfg=cfg(mozjpeg_layout_probe)");
+    let probe = layout_probe(&config_dir, &vendor, &target_arch, &target_feature, &out_dir).and_then(|probe| {
+        let (mut p, _) = compiler(&config_dir, &vendor, &target_arch, &target_feature);
+        p.file(probe).try_compile("mozjpeg_layout_probe").map_err(|e| e.to_string())
+    });
+    match probe {
+        Ok(()) => println!("cargo:rustc-cfg=mozjpeg_layout_probe"),
+        Err(err) => println!("cargo:warning=struct layout check disabled: {err}"),
+    }
+
+    if cfg!(feature = "precision12") {
+        build_precision12(&config_dir, &vendor, &target_arch, &target_feature, files, abi);
+    }
+}
+
+/// Structs bound in `src/lib.rs`, with their names in C
+const BOUND_STRUCTS: &[(&str, &str)] = &[
+    ("JQUANT_TBL", "JQUANT_TBL"),
+    ("JHUFF_TBL", "JHUFF_TBL"),
+    ("jpeg_component_info", "jpeg_component_info"),
+    ("jpeg_scan_info", "jpeg_scan_info"),
+    ("jpeg_marker_struct", "struct jpeg_marker_struct"),
+    ("jpeg_common_struct", "struct jpeg_common_struct"),
+    ("jpeg_compress_struct", "struct jpeg_compress_struct"),
+    ("jpeg_decompress_struct", "struct jpeg_decompress_struct"),
+    ("jpeg_error_mgr", "struct jpeg_error_mgr"),
+    ("jpeg_progress_mgr", "struct jpeg_progress_mgr"),
+    ("jpeg_destination_mgr", "struct jpeg_destination_mgr"),
+    ("jpeg_source_mgr", "struct jpeg_source_mgr"),
+    ("jpeg_memory_mgr", "struct jpeg_memory_mgr"),
+];
+
+/// Generates a C table of sizes, alignments and field offsets of the bound structs, as compiled for the target
+/// with the current `JPEG_LIB_VERSION`, and `layout_probe.rs` listing the same fields for the test in `src/layout.rs`.
+///
+/// Fields are taken from the preprocessed `jpeglib.h`, so the Rust structs must have all of them.
+fn layout_probe(config_dir: &Path, vendor: &Path, target_arch: &str, target_feature: &str, out_dir: &Path) -> Result<PathBuf, String> {
+    let mut structs = BOUND_STRUCTS.to_vec();
+    let mut includes = String::from("#include <stddef.h>\n#include <stdio.h>\n#include \"jpeglib.h\"\n");
+    if cfg!(feature = "jpegtran") {
+        structs.push(("jpeg_transform_info", "jpeg_transform_info"));
+        includes.push_str("#include \"transupp.h\"\n");
+    }
+
+    let header_path = out_dir.join("layout_probe.h.c");
+    fs::write(&header_path, &includes).expect("layout_probe.h.c");
+    let (mut c, _) = compiler(config_dir, vendor, target_arch, target_feature);
+    let header = c.file(&header_path).try_expand().map_err(|e| e.to_string())?;
+    let header = String::from_utf8_lossy(&header);
+    // skips line markers
+    let header = header.lines().filter(|line| !line.trim_start().starts_with('#')).collect::<Vec<_>>().join("\n");
+
+    let mut probe_c = includes;
+    // alignment is the offset after a `char`
+    for (rust_name, c_name) in &structs {
+        probe_c.push_str(&format!("struct mozjpeg_align_{rust_name} {{ char c; {c_name} s; }};\n"));
+    }
+    probe_c.push_str("static const size_t layout[] = {\n");
+    let mut probe_rs = String::from("layout_probe! {\n");
+    for (rust_name, c_name) in structs {
+        let fields = struct_fields(&header, c_name).ok_or_else(|| format!("{c_name} not found in jpeglib.h"))?;
+        probe_c.push_str(&format!("    sizeof({c_name}),\n    offsetof(struct mozjpeg_align_{rust_name}, s),\n"));
+        probe_rs.push_str(&format!("    {rust_name}:"));
+        for field in &fields {
+            probe_c.push_str(&format!("    offsetof({c_name}, {field}),\n"));
+            // Rust has `jpeg_common_fields` in a `jpeg_common_struct`
+            let common = rust_name != "jpeg_common_struct" && COMMON_FIELDS.contains(&field.as_str());
+            probe_rs.push_str(&format!(" {}{field}", if common { "common." } else { "" }));
+        }
+        probe_rs.push_str(";\n");
+    }
+    probe_c.push_str("};\nconst size_t *mozjpeg_layout_probe(size_t *len) {\n    *len = sizeof(layout) / sizeof(layout[0]);\n    return layout;\n}\n");
+    probe_rs.push_str("}\n");
+
+    fs::write(out_dir.join("layout_probe.rs"), probe_rs).expect("layout_probe.rs");
+    let probe_path = out_dir.join("layout_probe.c");
+    fs::write(&probe_path, probe_c).expect("layout_probe.c");
+    Ok(probe_path)
+}
+
+/// Expansion of the `jpeg_common_fields` macro
+const COMMON_FIELDS: &[&str] = &["err", "mem", "progress", "client_data", "is_decompressor", "global_state"];
+
+/// Field names of `struct name {…}` or `typedef struct {…} name;` in preprocessed C
+fn struct_fields(source: &str, c_name: &str) -> Option<Vec<String>> {
+    let source = source.split_whitespace().collect::<Vec<_>>().join(" ");
+    let body = if let Some(tag) = c_name.strip_prefix("struct ") {
+        let start = source.find(&format!("struct {tag} {{"))? + tag.len() + 9;
+        let len = matching_brace(&source[start..])?;
+        &source[start..start + len]
+    } else {
+        let end = source.find(&format!("}} {c_name};"))?;
+        let mut depth = 0;
+        let start = source[..end].rfind(|c| {
+            match c {
+                '}' => depth += 1,
+                '{' if depth == 0 => return true,
+                '{' => depth -= 1,
+                _ => {},
+            }
+            false
+        })? + 1;
+        &source[start..end]
+    };
+
+    let mut fields = Vec::new();
+    for decl in split_top_level(body, ';') {
+        // function pointers are `ret (*name) (args)`
+        if let Some(pos) = decl.find("(*") {
+            let name = decl[pos + 2..].split(')').next()?.trim();
+            fields.push(name.to_string());
+            continue;
+        }
+        // nested `union {…} name` and declarators like `*name[N]`
+        let decl = match decl.rfind('}') {
+            Some(pos) => &decl[pos + 1..],
+            None => decl,
+        };
+        for declarator in split_top_level(decl, ',') {
+            let declarator = declarator.split('[').next()?;
+            let name = declarator.rsplit(|c: char| !c.is_ascii_alphanumeric() && c != '_').next()?;
+            if !name.is_empty() {
+                fields.push(name.to_string());
+            }
+        }
+    }
+    Some(fields)
+}
+
+/// Length of the text before the `}` closing an already open `{`
+fn matching_brace(source: &str) -> Option<usize> {
+    let mut depth = 0;
+    source.find(|c| {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return true,
+            '}' => depth -= 1,
+            _ => {},
+        }
+        false
+    })
+}
+
+/// Non-empty parts of `source` separated by `sep` outside of any brackets
+fn split_top_level(source: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (pos, c) in source.char_indices() {
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push(source[start..pos].trim());
+                start = pos + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(source[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// Builds the library again with `BITS_IN_JSAMPLE 12` and every global symbol prefixed with `mozjpeg12_`,
+/// so that it can be linked together with the 8-bit build. See `src/precision12.rs`.
+///
+/// There's no SIMD for 12-bit samples. The memory manager backend (`jmemsys.h`) is shared with the 8-bit build.
+fn build_precision12(config_dir: &Path, vendor: &Path, target_arch: &str, target_feature: &str, files: &[&str], abi: &str) {
+    let config_dir12 = config_dir.with_file_name("include12");
+    let _ = fs::create_dir_all(&config_dir12);
+
+    let mut sources: Vec<&str> = files.to_vec();
+    sources.push("vendor/jsimd_none.c");
+    if cfg!(feature = "icc_io") {
+        sources.extend(["vendor/jcicc.c", "vendor/jdicc.c"]);
+    }
+    if cfg!(feature = "arith_enc") {
+        sources.push("vendor/jcarith.c");
+    }
+    if cfg!(feature = "arith_dec") {
+        sources.push("vendor/jdarith.c");
+    }
+    if cfg!(feature = "arith_enc") || cfg!(feature = "arith_dec") {
+        sources.push("vendor/jaricom.c");
+    }
+
+    let shared = global_symbols(&vendor.join("jmemsys.h"));
+    let mut symbols = library_symbols(vendor, &sources);
+    symbols.retain(|name| !shared.contains(name));
+
+    // keeps `prefix_symbols` renames of the shared functions
+    let renamed_here = |line: &str| line.strip_prefix("#define ")
+        .and_then(|def| def.split_whitespace().next())
+        .is_some_and(|name| symbols.iter().any(|s| s == name));
+    let jconfig_h = fs::read_to_string(config_dir.join("jconfig.h")).expect("jconfig");
+    let mut jconfig_h = jconfig_h.lines()
+        .filter(|line| !line.contains("WITH_SIMD") && !renamed_here(line.trim_start()))
+        .map(|line| line.replace("BITS_IN_JSAMPLE 8", "BITS_IN_JSAMPLE 12") + "\n")
+        .collect::<String>();
+    for name in &symbols {
+        jconfig_h.push_str(&format!("#define {name} mozjpeg12_{name}\n"));
+    }
+    fs::write(config_dir12.join("jconfig.h"), jconfig_h).expect("jconfig12");
+
+    let (mut c, _) = compiler(&config_dir12, vendor, target_arch, target_feature);
+    // `jconfigint.h` and `jversion.h`
+    c.include(config_dir);
+    for file in sources {
+        c.file(file);
+    }
+    c.compile(&format!("mozjpeg12_{abi}"));
+}
+
+/// Every global symbol of the C library: functions and tables declared in its headers
+/// (but not the command-line tools' `cdjpeg.h`), and functions defined in `sources`
+fn library_symbols(vendor: &Path, sources: &[&str]) -> Vec<String> {
+    let headers = fs::read_dir(vendor).expect("vendor").filter_map(|e| {
+        let path = e.ok()?.path();
+        (path.extension().is_some_and(|e| e == "h") && !path.ends_with("cdjpeg.h")).then_some(path)
+    });
+    let mut symbols: Vec<String> = headers
+        .chain([vendor.join("simd").join("jsimd.h")])
+        .chain(sources.iter().map(PathBuf::from))
+        .flat_map(|path| global_symbols(&path))
+        // the only exported variable that isn't declared in a header
+        .chain(["jpeg_std_message_table".into()])
+        .collect();
+    symbols.sort();
+    symbols.dedup();
+    symbols
+}
+
+/// Names of functions declared with `GLOBAL()`, `EXTERN()` or `DLLEXPORT` (TurboJPEG), and of `extern const` tables
+fn global_symbols(path: &Path) -> Vec<String> {
+    let source = fs::read_to_string(path).unwrap_or_default();
+    let mut names = Vec::new();
+    for (pos, _) in source.match_indices("GLOBAL(").chain(source.match_indices("EXTERN(")) {
+        let Some(end) = source[pos..].find(')') else { continue };
+        let rest = source[pos + end + 1..].trim_start();
+        let name_len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+        // skips macro definitions
+        if name_len > 0 && rest[name_len..].trim_start().starts_with('(') {
+            names.push(rest[..name_len].to_string());
+        }
+    }
+    for line in source.lines().filter(|line| line.starts_with("DLLEXPORT ") || line.starts_with("extern const ")) {
+        let Some(decl) = line.split(['(', '[']).next() else { continue };
+        let name = decl.rsplit(|c: char| !c.is_ascii_alphanumeric() && c != '_').next().unwrap_or_default();
+        if !name.is_empty() {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// `jsimd.c` with its `JSIMD_FORCE*` overrides read from `force_instruction_set()` instead of the environment.
+/// `NO_GETENV` stays defined for the rest of the library.
+#[cfg(feature = "with_simd")]
+fn jsimd_c(config_dir: &Path, vendor: &Path, arch_path: &str) -> PathBuf {
+    let path = config_dir.join(format!("jsimd-{}.c", arch_path.replace('/', "-")));
+    fs::write(&path, format!(r#"
+        #include "jinclude.h"
+        #undef NO_GETENV
+        #define GETENV_S mozjpeg_sys_simd_getenv_s
+        int mozjpeg_sys_simd_getenv_s(char *buffer, size_t buffer_size, const char *name);
+        #include "{}"
+        "#, vendor.join("simd").join(arch_path).display().to_string().replace('\\', "/"))).expect("jsimd.c");
+    path
+}
+
+/// MozJPEG has SIMD code only for these
+fn asm_simd_supported(target_arch: &str) -> bool {
+    let supported = matches!(target_arch, "x86_64" | "x86" | "arm" | "aarch64" | "mips" | "powerpc" | "powerpc64");
+    if !supported && cfg!(feature = "with_simd") && !cfg!(feature = "portable_simd") {
+        println!("cargo:warning=MozJPEG has no SIMD code for {target_arch}. Enable the `portable_simd` feature instead.");
+    }
+    supported
 }
 
 fn gas_supported(c: &cc::Build) -> bool {
@@ -357,6 +697,10 @@ fn build_gas(mut c: cc::Build, target_arch: &str, abi: &str) {
         },
     });
     c.flag("-xassembler-with-cpp");
+    if cfg!(feature = "prefix_symbols") {
+        // the renames
+        c.flag("-include").flag("jconfig.h");
+    }
 
     c.compile(&format!("mozjpegsimd{abi}"));
 }
@@ -372,13 +716,19 @@ fn build_nasm(root: &Path, vendor_dir: &Path, out_dir: &Path, target_arch: &str,
 
     n.define("PIC", None); // Rust always uses -fPIC
 
-    match (target_os, target_arch.ends_with("64")) {
-        ("windows", false) => n.define("WIN32", None),
-        ("windows", true) => n.define("WIN64", None),
-        ("macos" | "ios", _) => n.define("MACHO", None),
-        _ => n.define("ELF", None),
+    // `jsimdext.inc` adds `_` to symbol names in some formats
+    let underscored = match (target_os, target_arch.ends_with("64")) {
+        ("windows", false) => { n.define("WIN32", None); true },
+        ("windows", true) => { n.define("WIN64", None); false },
+        ("macos" | "ios", _) => { n.define("MACHO", None); true },
+        _ => { n.define("ELF", None); false },
     };
 
+    if cfg!(feature = "prefix_symbols") {
+        // the same names as the renames in `jconfig.h`
+        n.flag("--prefix").flag(if underscored { "_mozjpeg" } else { "mozjpeg_" });
+    }
+
     let arch_name = match target_arch {
         "x86" => "i386",
         "x86_64" => {
@@ -0,0 +1,210 @@
+use crate::*;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Failure reported by the safe helpers in this crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// libjpeg has aborted with a fatal error (`ERREXIT`)
+    Libjpeg {
+        /// One of the `JERR_*` codes
+        code: J_MESSAGE_CODE,
+        /// Formatted message, as libjpeg would print it
+        message: String,
+    },
+    /// The arguments can't be used with the given image
+    InvalidArgument(&'static str),
+    /// The images can't be combined losslessly
+    Incompatible(String),
+    /// libjpeg has reported a warning, and `ErrorMgr::abort_on()` made it fatal
+    Warning(Warning),
+    /// The progress callback has stopped the operation
+    Cancelled,
+    /// The file exceeds one of the `DecodeLimits`
+    LimitExceeded(Limit),
+    /// An allocation would exceed the limit set with `set_memory_limit()`
+    OutOfMemory {
+        /// Size of the allocation that failed, in bytes
+        requested: usize,
+        /// The memory limit, in bytes
+        limit: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Libjpeg { message, .. } => f.write_str(message),
+            Self::InvalidArgument(msg) => f.write_str(msg),
+            Self::Incompatible(msg) => write!(f, "incompatible images: {msg}"),
+            Self::Warning(warning) => f.write_str(&warning.message),
+            Self::Cancelled => f.write_str("cancelled"),
+            Self::LimitExceeded(Limit::Scans(n)) => write!(f, "too many scans ({n})"),
+            Self::LimitExceeded(Limit::Pixels(n)) => write!(f, "image too large ({n} pixels)"),
+            Self::LimitExceeded(Limit::Deadline) => f.write_str("decoding took too long"),
+            Self::OutOfMemory { requested, limit } => write!(f, "out of memory (allocating {requested} bytes with a limit of {limit})"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// Reads the current message from the error manager of `cinfo`
+    pub(crate) unsafe fn from_common(cinfo: &mut jpeg_common_struct) -> Self {
+        Self::Libjpeg {
+            code: (*cinfo.err).msg_code,
+            message: (*cinfo.err).message(),
+        }
+    }
+}
+
+/// Non-fatal problem reported by libjpeg (`WARNMS`), e.g. corrupt or truncated data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// One of the `JWRN_*` codes
+    pub code: J_MESSAGE_CODE,
+    /// Formatted message, as libjpeg would print it
+    pub message: String,
+    /// Integer parameters of the message (`msg_parm.i`). Their meaning depends on the `code`.
+    pub params: [c_int; 8],
+    /// Approximate byte offset in the input file, if known
+    pub offset: Option<usize>,
+}
+
+type WarningCallback = dyn FnMut(&Warning) -> bool;
+
+/// Error manager that collects warnings instead of printing them, and reports fatal errors as `Error`.
+///
+/// Install it with `cinfo.common.err = err.as_iface()`, and call libjpeg functions inside `catch()`.
+/// Errors and aborted warnings unwind with a `Box<Error>` panic payload, so the `unwinding` feature is required.
+///
+/// By default all warnings are allowed. Use `abort_on()` or `strict()` to make some of them fatal.
+#[repr(C)]
+pub struct ErrorMgr {
+    iface: jpeg_error_mgr,
+    warnings: Vec<Warning>,
+    abort_on: Option<Box<WarningCallback>>,
+    /// Size of the input, for reporting positions of warnings
+    pub(crate) input_len: Option<usize>,
+}
+
+impl ErrorMgr {
+    #[must_use]
+    pub fn new() -> Box<Self> {
+        unsafe {
+            let mut err = Box::new(Self {
+                iface: mem::zeroed(),
+                warnings: Vec::new(),
+                abort_on: None,
+                input_len: None,
+            });
+            jpeg_std_error(&mut err.iface);
+            err.iface.error_exit = Some(unwind_error_exit);
+            err.iface.emit_message = Some(Self::emit_message);
+            err.iface.output_message = Some(silent_output_message);
+            err
+        }
+    }
+
+    /// Pointer for `cinfo.common.err`. The `ErrorMgr` must outlive the `cinfo`.
+    pub fn as_iface(&mut self) -> &mut jpeg_error_mgr {
+        &mut self.iface
+    }
+
+    /// Decides per warning whether it should abort with `Error::Warning`
+    ///
+    /// The callback returns `true` to abort. The warning is recorded either way.
+    pub fn abort_on(&mut self, callback: impl FnMut(&Warning) -> bool + 'static) {
+        self.abort_on = Some(Box::new(callback));
+    }
+
+    /// Aborts on every warning
+    pub fn strict(&mut self) {
+        self.abort_on(|_| true);
+    }
+
+    /// Warnings collected so far, in order they were reported
+    #[must_use]
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        mem::take(&mut self.warnings)
+    }
+
+    unsafe fn from_common(cinfo: &mut jpeg_common_struct) -> &mut Self {
+        &mut *cinfo.err.cast::<Self>()
+    }
+
+    /// Sets `trace_level`. With the `log` feature, messages up to this level are sent to `log::trace!`.
+    ///
+    /// Level 1 traces markers and tables, 2 also Huffman tables, 3 also every marker and restart.
+    pub fn set_trace_level(&mut self, level: c_int) {
+        self.iface.trace_level = level;
+    }
+
+    /// Collects warnings instead of printing them
+    unsafe extern "C-unwind" fn emit_message(cinfo: &mut jpeg_common_struct, msg_level: c_int) {
+        if msg_level >= 0 {
+            #[cfg(feature = "log")]
+            if msg_level <= (*cinfo.err).trace_level && log::log_enabled!(target: "mozjpeg", log::Level::Trace) {
+                let code = (*cinfo.err).msg_code;
+                log::trace!(target: "mozjpeg", code; "{}", (*cinfo.err).message());
+            }
+            return;
+        }
+        let message = (*cinfo.err).message();
+        #[cfg(feature = "log")]
+        log::warn!(target: "mozjpeg", code = (*cinfo.err).msg_code; "{message}");
+        let offset = if cinfo.is_decompressor != 0 {
+            let src = (*(cinfo as *mut jpeg_common_struct).cast::<jpeg_decompress_struct>()).src;
+            Self::from_common(cinfo).input_len
+                .filter(|_| !src.is_null())
+                .map(|len| len.saturating_sub((*src).bytes_in_buffer))
+        } else {
+            None
+        };
+        let err = Self::from_common(cinfo);
+        err.iface.num_warnings += 1;
+        let warning = Warning {
+            code: err.iface.msg_code,
+            message,
+            params: err.iface.msg_parm.int_params(),
+            offset,
+        };
+        let abort = err.abort_on.as_mut().is_some_and(|abort_on| abort_on(&warning));
+        err.warnings.push(warning);
+        if abort {
+            let warning = err.warnings.last().cloned().unwrap();
+            panic::resume_unwind(Box::new(Error::Warning(warning)));
+        }
+    }
+}
+
+/// `error_exit` that unwinds to the nearest `catch()` instead of calling `exit()`
+unsafe extern "C-unwind" fn unwind_error_exit(cinfo: &mut jpeg_common_struct) {
+    let err = Error::from_common(cinfo);
+    #[cfg(feature = "log")]
+    if let Error::Libjpeg { code, message } = &err {
+        log::error!(target: "mozjpeg", code = *code; "{message}");
+    }
+    panic::resume_unwind(Box::new(err));
+}
+
+/// `output_message` that doesn't print to stderr
+unsafe extern "C-unwind" fn silent_output_message(_cinfo: &mut jpeg_common_struct) {}
+
+/// Runs `f`, converting libjpeg errors raised via `ErrorMgr` into `Err`.
+/// Other panics are propagated.
+pub fn catch<T>(f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(res) => res,
+        Err(payload) => match payload.downcast::<Error>() {
+            Ok(err) => Err(*err),
+            Err(payload) => panic::resume_unwind(payload),
+        },
+    }
+}
@@ -0,0 +1,238 @@
+//! Implementation of `jmemsys.h` (replacing `jmemnobs.c`) that allocates with the Rust global allocator.
+//! It's always used on wasm32-unknown-unknown, which has no `malloc`.
+//!
+//! With the `backing_store` feature, virtual arrays that don't fit in `max_memory_to_use`
+//! are kept in temporary files. Otherwise, like `jmemnobs.c`, it fails with `JERR_NO_BACKING_STORE`.
+use crate::*;
+use std::alloc::{self, Layout};
+#[cfg(feature = "backing_store")]
+use std::fs::{self, File};
+#[cfg(feature = "backing_store")]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "backing_store")]
+use std::path::PathBuf;
+#[cfg(feature = "backing_store")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Allocations are prefixed with their size, so that freeing doesn't rely on libjpeg's bookkeeping
+const HEADER: usize = 16;
+
+unsafe fn allocate(size: usize) -> *mut c_void {
+    let Some(layout) = size.checked_add(HEADER).and_then(|size| Layout::from_size_align(size, HEADER).ok()) else {
+        return std::ptr::null_mut();
+    };
+    let ptr = alloc::alloc(layout);
+    if ptr.is_null() {
+        return ptr.cast(); // libjpeg will report JERR_OUT_OF_MEMORY
+    }
+    ptr.cast::<usize>().write(layout.size());
+    ptr.add(HEADER).cast()
+}
+
+unsafe fn deallocate(object: *mut c_void) {
+    if object.is_null() {
+        return;
+    }
+    let ptr = object.cast::<u8>().sub(HEADER);
+    let size = ptr.cast::<usize>().read();
+    alloc::dealloc(ptr, Layout::from_size_align_unchecked(size, HEADER));
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_get_small")]
+unsafe extern "C" fn jpeg_get_small(_cinfo: &mut jpeg_common_struct, sizeofobject: usize) -> *mut c_void {
+    allocate(sizeofobject)
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_free_small")]
+unsafe extern "C" fn jpeg_free_small(_cinfo: &mut jpeg_common_struct, object: *mut c_void, _sizeofobject: usize) {
+    deallocate(object);
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_get_large")]
+unsafe extern "C" fn jpeg_get_large(_cinfo: &mut jpeg_common_struct, sizeofobject: usize) -> *mut c_void {
+    allocate(sizeofobject)
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_free_large")]
+unsafe extern "C" fn jpeg_free_large(_cinfo: &mut jpeg_common_struct, object: *mut c_void, _sizeofobject: usize) {
+    deallocate(object);
+}
+
+/// Everything fits, unless `max_memory_to_use` is set
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_mem_available")]
+unsafe extern "C" fn jpeg_mem_available(cinfo: &mut jpeg_common_struct, _min_bytes_needed: usize, max_bytes_needed: usize, already_allocated: usize) -> usize {
+    let max_memory_to_use = (*cinfo.mem).max_memory_to_use;
+    if max_memory_to_use > 0 {
+        (max_memory_to_use as usize).saturating_sub(already_allocated)
+    } else {
+        max_bytes_needed
+    }
+}
+
+#[cfg(not(feature = "backing_store"))]
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_open_backing_store")]
+unsafe extern "C-unwind" fn jpeg_open_backing_store(cinfo: &mut jpeg_common_struct, _info: *mut c_void, _total_bytes_needed: c_long) {
+    cinfo.error_exit(JERR_NO_BACKING_STORE, &[]);
+}
+
+/// `backing_store_info` from `jmemsys.h`. libjpeg allocates it, and only uses the methods.
+#[cfg(feature = "backing_store")]
+#[repr(C)]
+struct BackingStoreInfo {
+    read_backing_store: Option<unsafe extern "C-unwind" fn(&mut jpeg_common_struct, &mut BackingStoreInfo, *mut c_void, c_long, c_long)>,
+    write_backing_store: Option<unsafe extern "C-unwind" fn(&mut jpeg_common_struct, &mut BackingStoreInfo, *mut c_void, c_long, c_long)>,
+    close_backing_store: Option<unsafe extern "C-unwind" fn(&mut jpeg_common_struct, &mut BackingStoreInfo)>,
+    /// `FILE *temp_file` in C
+    temp_file: *mut TempFile,
+    temp_name: [u8; 64],
+}
+
+#[cfg(feature = "backing_store")]
+struct TempFile {
+    file: File,
+    /// Set if the file has to be deleted after closing
+    path: Option<PathBuf>,
+}
+
+/// Sets the `%s` parameter of the message
+#[cfg(feature = "backing_store")]
+unsafe fn set_str_param(cinfo: &mut jpeg_common_struct, param: &[u8]) {
+    // the union is 80 bytes, same as `s`
+    let s = &mut *std::ptr::addr_of_mut!((*cinfo.err).msg_parm._bindgen_data_).cast::<[u8; 80]>();
+    let len = param.len().min(s.len() - 1);
+    s[..len].copy_from_slice(&param[..len]);
+    s[len] = 0;
+}
+
+#[cfg(feature = "backing_store")]
+unsafe fn trace_temp_file(cinfo: &mut jpeg_common_struct, code: J_MESSAGE_CODE, info: &BackingStoreInfo) {
+    let len = info.temp_name.iter().position(|&c| c == 0).unwrap_or(0);
+    set_str_param(cinfo, &info.temp_name[..len]);
+    (*cinfo.err).msg_code = code;
+    if let Some(emit_message) = (*cinfo.err).emit_message {
+        emit_message(cinfo, 1);
+    }
+}
+
+/// Creates a temporary file, which is deleted when closed (or right away on Unix)
+#[cfg(feature = "backing_store")]
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_open_backing_store")]
+unsafe extern "C-unwind" fn jpeg_open_backing_store(cinfo: &mut jpeg_common_struct, info: &mut BackingStoreInfo, _total_bytes_needed: c_long) {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut attempts = 0;
+    let (name, path, file) = loop {
+        let name = format!("mozjpeg-{}-{}.tmp", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(&name);
+        match File::options().read(true).write(true).create_new(true).open(&path) {
+            Ok(file) => break (name, path, file),
+            // a stale file left by an earlier process with the same PID
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 100 => attempts += 1,
+            Err(_) => {
+                set_str_param(cinfo, path.to_string_lossy().as_bytes());
+                cinfo.error_exit(JERR_TFILE_CREATE, &[]);
+            },
+        }
+    };
+    let path = if cfg!(unix) && fs::remove_file(&path).is_ok() { None } else { Some(path) };
+
+    let len = name.len().min(info.temp_name.len() - 1);
+    info.temp_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    info.temp_name[len] = 0;
+    info.temp_file = Box::into_raw(Box::new(TempFile { file, path }));
+    info.read_backing_store = Some(read_backing_store);
+    info.write_backing_store = Some(write_backing_store);
+    info.close_backing_store = Some(close_backing_store);
+    trace_temp_file(cinfo, JTRC_TFILE_OPEN, info);
+}
+
+#[cfg(feature = "backing_store")]
+unsafe extern "C-unwind" fn read_backing_store(cinfo: &mut jpeg_common_struct, info: &mut BackingStoreInfo, buffer_address: *mut c_void, file_offset: c_long, byte_count: c_long) {
+    let file = &mut (*info.temp_file).file;
+    if file.seek(SeekFrom::Start(file_offset as u64)).is_err() {
+        cinfo.error_exit(JERR_TFILE_SEEK, &[]);
+    }
+    let buffer = std::slice::from_raw_parts_mut(buffer_address.cast::<u8>(), byte_count as usize);
+    if file.read_exact(buffer).is_err() {
+        cinfo.error_exit(JERR_TFILE_READ, &[]);
+    }
+}
+
+#[cfg(feature = "backing_store")]
+unsafe extern "C-unwind" fn write_backing_store(cinfo: &mut jpeg_common_struct, info: &mut BackingStoreInfo, buffer_address: *mut c_void, file_offset: c_long, byte_count: c_long) {
+    let file = &mut (*info.temp_file).file;
+    if file.seek(SeekFrom::Start(file_offset as u64)).is_err() {
+        cinfo.error_exit(JERR_TFILE_SEEK, &[]);
+    }
+    let buffer = std::slice::from_raw_parts(buffer_address.cast::<u8>(), byte_count as usize);
+    if file.write_all(buffer).is_err() {
+        cinfo.error_exit(JERR_TFILE_WRITE, &[]);
+    }
+}
+
+#[cfg(feature = "backing_store")]
+unsafe extern "C-unwind" fn close_backing_store(cinfo: &mut jpeg_common_struct, info: &mut BackingStoreInfo) {
+    let temp = Box::from_raw(info.temp_file);
+    info.temp_file = std::ptr::null_mut();
+    drop(temp.file);
+    if let Some(path) = temp.path {
+        let _ = fs::remove_file(path);
+    }
+    trace_temp_file(cinfo, JTRC_TFILE_CLOSE, info);
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_mem_init")]
+extern "C" fn jpeg_mem_init(_cinfo: &mut jpeg_common_struct) -> c_long {
+    0
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jpeg_mem_term")]
+extern "C" fn jpeg_mem_term(_cinfo: &mut jpeg_common_struct) {}
+
+/// wasm32-unknown-unknown has no libc, but `jpeg_mem_dest` and ICC functions need `malloc`
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod libc_alloc {
+    use super::*;
+
+    #[no_mangle]
+    unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+        allocate(size)
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn calloc(nmemb: usize, size: usize) -> *mut c_void {
+        let Some(size) = nmemb.checked_mul(size) else { return std::ptr::null_mut() };
+        let ptr = allocate(size);
+        if !ptr.is_null() {
+            ptr.cast::<u8>().write_bytes(0, size);
+        }
+        ptr
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn realloc(object: *mut c_void, size: usize) -> *mut c_void {
+        if object.is_null() {
+            return allocate(size);
+        }
+        let old_size = object.cast::<u8>().sub(HEADER).cast::<usize>().read() - HEADER;
+        let ptr = allocate(size);
+        if !ptr.is_null() {
+            ptr.cast::<u8>().copy_from_nonoverlapping(object.cast(), old_size.min(size));
+            deallocate(object);
+        }
+        ptr
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn free(object: *mut c_void) {
+        deallocate(object);
+    }
+}
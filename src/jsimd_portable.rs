@@ -0,0 +1,223 @@
+//! Portable backend of `jsimd_rust.rs`, with the `portable_simd` feature, for targets without MozJPEG's assembly.
+//!
+//! Kernels work on arrays of 8 or 16 lanes with no dependencies between them,
+//! which LLVM turns into the target's vector instructions (e.g. RVV on riscv64 with `-C target-feature=+v`).
+use crate::jsimd_rust::*;
+use crate::*;
+
+/// Applies `f` to each lane
+#[inline(always)]
+fn lanes<T: Copy, const N: usize>(f: impl FnMut(usize) -> T) -> [T; N] {
+    std::array::from_fn(f)
+}
+
+pub(crate) unsafe fn rgb_ycc_row(input: &[u8], outputs: [*mut u8; 3], width: usize, offsets: [usize; 3], pixel_size: usize) {
+    let chunks = width / 16;
+    for chunk in 0..chunks {
+        let pixels = &input[chunk * 16 * pixel_size..][..16 * pixel_size];
+        let [r, g, b] = offsets.map(|o| lanes::<i32, 16>(|i| i32::from(pixels[i * pixel_size + o])));
+        let y = lanes::<u8, 16>(|i| ((FIX_0_29900 * r[i] + FIX_0_58700 * g[i] + FIX_0_11400 * b[i] + ONE_HALF) >> SCALEBITS) as u8);
+        let cb = lanes::<u8, 16>(|i| ((-FIX_0_16874 * r[i] - FIX_0_33126 * g[i] + FIX_0_50000 * b[i] + CBCR_OFFSET + ONE_HALF - 1) >> SCALEBITS) as u8);
+        let cr = lanes::<u8, 16>(|i| ((FIX_0_50000 * r[i] - FIX_0_41869 * g[i] - FIX_0_08131 * b[i] + CBCR_OFFSET + ONE_HALF - 1) >> SCALEBITS) as u8);
+        for (out, res) in outputs.iter().zip([y, cb, cr]) {
+            out.add(chunk * 16).cast::<[u8; 16]>().write_unaligned(res);
+        }
+    }
+    rgb_ycc_tail(input, outputs, chunks * 16, width, offsets, pixel_size);
+}
+
+/// `output_cols` is a multiple of `DCTSIZE`
+pub(crate) unsafe fn h2v1_downsample_row(input: *const u8, output: *mut u8, output_cols: usize) {
+    for col in (0..output_cols).step_by(8) {
+        let pixels = input.add(col * 2).cast::<[u8; 16]>().read_unaligned();
+        // bias = 0,1,0,1,… for successive samples
+        let avg = lanes::<u8, 8>(|i| ((u16::from(pixels[i * 2]) + u16::from(pixels[i * 2 + 1]) + (i & 1) as u16) >> 1) as u8);
+        output.add(col).cast::<[u8; 8]>().write_unaligned(avg);
+    }
+}
+
+pub(crate) unsafe fn h2v2_downsample_row(input0: *const u8, input1: *const u8, output: *mut u8, output_cols: usize) {
+    for col in (0..output_cols).step_by(8) {
+        let p0 = input0.add(col * 2).cast::<[u8; 16]>().read_unaligned();
+        let p1 = input1.add(col * 2).cast::<[u8; 16]>().read_unaligned();
+        // bias = 1,2,1,2,… for successive samples
+        let avg = lanes::<u8, 8>(|i| {
+            let sum = u16::from(p0[i * 2]) + u16::from(p0[i * 2 + 1]) + u16::from(p1[i * 2]) + u16::from(p1[i * 2 + 1]);
+            ((sum + 1 + (i & 1) as u16) >> 2) as u8
+        });
+        output.add(col).cast::<[u8; 8]>().write_unaligned(avg);
+    }
+}
+
+/// `rows[k][i]` is the `k`-th element of the `i`-th row, so that lanes are the rows (or, transposed, columns)
+fn transpose<T: Copy>(rows: [[T; 8]; 8]) -> [[T; 8]; 8] {
+    lanes(|k| lanes(|i| rows[i][k]))
+}
+
+unsafe fn load_block(data: *const DCTELEM) -> [[DCTELEM; 8]; 8] {
+    data.cast::<[[DCTELEM; 8]; 8]>().read_unaligned()
+}
+
+unsafe fn store_block(data: *mut DCTELEM, rows: [[DCTELEM; 8]; 8]) {
+    data.cast::<[[DCTELEM; 8]; 8]>().write_unaligned(rows);
+}
+
+type I32x8 = [i32; 8];
+
+#[inline(always)]
+fn add(a: I32x8, b: I32x8) -> I32x8 {
+    lanes(|i| a[i] + b[i])
+}
+
+#[inline(always)]
+fn sub(a: I32x8, b: I32x8) -> I32x8 {
+    lanes(|i| a[i] - b[i])
+}
+
+#[inline(always)]
+fn mul(a: I32x8, c: i32) -> I32x8 {
+    lanes(|i| a[i] * c)
+}
+
+/// `DESCALE` from `jdct.h`
+#[inline(always)]
+fn descale(a: I32x8, n: u32) -> I32x8 {
+    lanes(|i| (a[i] + (1 << (n - 1))) >> n)
+}
+
+/// Pass 1 works on all rows at once, pass 2 on all columns
+pub(crate) unsafe fn fdct_islow(data: *mut DCTELEM) {
+    let rows = transpose(load_block(data)).map(|v| v.map(i32::from));
+    // pass 1 results are stored in `DCTELEM`s in C too
+    let columns = transpose(islow_pass(rows, true)).map(|v| v.map(|x| i32::from(x as DCTELEM)));
+    store_block(data, islow_pass(columns, false).map(|v| v.map(|x| x as DCTELEM)));
+}
+
+/// One pass of `jpeg_fdct_islow`
+#[inline(always)]
+fn islow_pass(d: [I32x8; 8], pass1: bool) -> [I32x8; 8] {
+    let bits = if pass1 { CONST_BITS - PASS1_BITS } else { CONST_BITS + PASS1_BITS };
+
+    let tmp0 = add(d[0], d[7]);
+    let tmp7 = sub(d[0], d[7]);
+    let tmp1 = add(d[1], d[6]);
+    let tmp6 = sub(d[1], d[6]);
+    let tmp2 = add(d[2], d[5]);
+    let tmp5 = sub(d[2], d[5]);
+    let tmp3 = add(d[3], d[4]);
+    let tmp4 = sub(d[3], d[4]);
+
+    let tmp10 = add(tmp0, tmp3);
+    let tmp13 = sub(tmp0, tmp3);
+    let tmp11 = add(tmp1, tmp2);
+    let tmp12 = sub(tmp1, tmp2);
+
+    let mut out = [[0; 8]; 8];
+    if pass1 {
+        out[0] = lanes(|i| (tmp10[i] + tmp11[i]) << PASS1_BITS);
+        out[4] = lanes(|i| (tmp10[i] - tmp11[i]) << PASS1_BITS);
+    } else {
+        out[0] = descale(add(tmp10, tmp11), PASS1_BITS);
+        out[4] = descale(sub(tmp10, tmp11), PASS1_BITS);
+    }
+
+    let z1 = mul(add(tmp12, tmp13), FIX_0_541196100);
+    out[2] = descale(add(z1, mul(tmp13, FIX_0_765366865)), bits);
+    out[6] = descale(add(z1, mul(tmp12, -FIX_1_847759065)), bits);
+
+    let z1 = add(tmp4, tmp7);
+    let z2 = add(tmp5, tmp6);
+    let z3 = add(tmp4, tmp6);
+    let z4 = add(tmp5, tmp7);
+    let z5 = mul(add(z3, z4), FIX_1_175875602);
+
+    let tmp4 = mul(tmp4, FIX_0_298631336);
+    let tmp5 = mul(tmp5, FIX_2_053119869);
+    let tmp6 = mul(tmp6, FIX_3_072711026);
+    let tmp7 = mul(tmp7, FIX_1_501321110);
+    let z1 = mul(z1, -FIX_0_899976223);
+    let z2 = mul(z2, -FIX_2_562915447);
+    let z3 = add(mul(z3, -FIX_1_961570560), z5);
+    let z4 = add(mul(z4, -FIX_0_390180644), z5);
+
+    out[7] = descale(add(add(tmp4, z1), z3), bits);
+    out[5] = descale(add(add(tmp5, z2), z4), bits);
+    out[3] = descale(add(add(tmp6, z2), z3), bits);
+    out[1] = descale(add(add(tmp7, z1), z4), bits);
+    out
+}
+
+pub(crate) unsafe fn fdct_ifast(data: *mut DCTELEM) {
+    let columns = transpose(ifast_pass(transpose(load_block(data))));
+    store_block(data, ifast_pass(columns));
+}
+
+type I16x8 = [i16; 8];
+
+/// One pass of `jpeg_fdct_ifast`, which is the same for rows and columns.
+/// Like in C, everything wraps to the 16-bit `DCTELEM`.
+#[inline(always)]
+fn ifast_pass(d: [I16x8; 8]) -> [I16x8; 8] {
+    let add = |a: I16x8, b: I16x8| -> I16x8 { lanes(|i| a[i].wrapping_add(b[i])) };
+    let sub = |a: I16x8, b: I16x8| -> I16x8 { lanes(|i| a[i].wrapping_sub(b[i])) };
+    // `MULTIPLY` truncates, because `USE_ACCURATE_ROUNDING` isn't defined
+    let mul = |a: I16x8, c: i32| -> I16x8 { lanes(|i| ((i32::from(a[i]) * c) >> 8) as i16) };
+
+    let tmp0 = add(d[0], d[7]);
+    let tmp7 = sub(d[0], d[7]);
+    let tmp1 = add(d[1], d[6]);
+    let tmp6 = sub(d[1], d[6]);
+    let tmp2 = add(d[2], d[5]);
+    let tmp5 = sub(d[2], d[5]);
+    let tmp3 = add(d[3], d[4]);
+    let tmp4 = sub(d[3], d[4]);
+
+    let tmp10 = add(tmp0, tmp3);
+    let tmp13 = sub(tmp0, tmp3);
+    let tmp11 = add(tmp1, tmp2);
+    let tmp12 = sub(tmp1, tmp2);
+
+    let mut out = [[0; 8]; 8];
+    out[0] = add(tmp10, tmp11);
+    out[4] = sub(tmp10, tmp11);
+
+    let z1 = mul(add(tmp12, tmp13), FIX_0_707106781);
+    out[2] = add(tmp13, z1);
+    out[6] = sub(tmp13, z1);
+
+    let tmp10 = add(tmp4, tmp5);
+    let tmp11 = add(tmp5, tmp6);
+    let tmp12 = add(tmp6, tmp7);
+
+    let z5 = mul(sub(tmp10, tmp12), FIX_0_382683433);
+    let z2 = add(mul(tmp10, FIX_0_541196100_FAST), z5);
+    let z4 = add(mul(tmp12, FIX_1_306562965), z5);
+    let z3 = mul(tmp11, FIX_0_707106781);
+
+    let z11 = add(tmp7, z3);
+    let z13 = sub(tmp7, z3);
+
+    out[5] = add(z13, z2);
+    out[3] = sub(z13, z2);
+    out[1] = add(z11, z4);
+    out[7] = sub(z11, z4);
+    out
+}
+
+/// `quantize()` from `jcdctmgr.c`, without the branch on the sign
+pub(crate) unsafe fn quantize(coef_block: *mut JCOEF, divisors: *const DCTELEM, workspace: *const DCTELEM) {
+    let load = |ptr: *const DCTELEM, i: usize| ptr.add(i).cast::<[DCTELEM; 8]>().read_unaligned();
+    for i in (0..DCTSIZE2).step_by(8) {
+        let coefs = load(workspace, i);
+        let recip = load(divisors, i);
+        let corr = load(divisors, i + DCTSIZE2);
+        let shift = load(divisors, i + DCTSIZE2 * 3);
+        let out = lanes::<JCOEF, 8>(|l| {
+            // `UDCTELEM2` is 32-bit
+            let product = (u32::from(coefs[l].unsigned_abs()) + u32::from(corr[l] as u16)).wrapping_mul(u32::from(recip[l] as u16));
+            let q = product.checked_shr(shift[l] as u32 + 16).unwrap_or(0) as JCOEF;
+            if coefs[l] < 0 { q.wrapping_neg() } else { q }
+        });
+        coef_block.add(i).cast::<[JCOEF; 8]>().write_unaligned(out);
+    }
+}
@@ -0,0 +1,237 @@
+//! Rust SIMD kernels, replacing `jsimd_none.c` with the `wasm_simd`, `portable_simd` or `x86_intrinsics_simd` feature.
+//!
+//! Implements color conversion (RGB to YCbCr), integer forward DCTs, quantization and
+//! h2v1/h2v2 downsampling. The results are identical to the C versions.
+//! Everything else reports as unsupported, and libjpeg uses the C code for it.
+//!
+//! This has the parts shared by the backends: `jsimd_wasm.rs` (SIMD128 intrinsics),
+//! `jsimd_x86.rs` (SSE2 and AVX2 intrinsics) and `jsimd_portable.rs` (plain Rust written for auto-vectorization).
+use crate::*;
+use crate::simd;
+
+#[cfg(all(feature = "wasm_simd", target_arch = "wasm32"))]
+use crate::jsimd_wasm as backend;
+#[cfg(mozjpeg_portable_simd)]
+use crate::jsimd_portable as backend;
+#[cfg(mozjpeg_x86_intrinsics_simd)]
+use crate::jsimd_x86 as backend;
+
+fn enabled() -> c_int {
+    c_int::from(simd::rust_instruction_set() != simd::InstructionSet::None)
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_can_rgb_ycc")]
+extern "C" fn jsimd_can_rgb_ycc() -> c_int {
+    enabled()
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_can_h2v1_downsample")]
+extern "C" fn jsimd_can_h2v1_downsample() -> c_int {
+    enabled()
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_can_h2v2_downsample")]
+extern "C" fn jsimd_can_h2v2_downsample() -> c_int {
+    enabled()
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_can_fdct_islow")]
+extern "C" fn jsimd_can_fdct_islow() -> c_int {
+    enabled()
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_can_fdct_ifast")]
+extern "C" fn jsimd_can_fdct_ifast() -> c_int {
+    enabled()
+}
+
+/// `jcdctmgr.c` falls back to C when a divisor can't be handled by the scaled multiply
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_can_quantize")]
+extern "C" fn jsimd_can_quantize() -> c_int {
+    enabled()
+}
+
+pub(crate) const SCALEBITS: u32 = 16;
+pub(crate) const ONE_HALF: i32 = 1 << (SCALEBITS - 1);
+pub(crate) const CBCR_OFFSET: i32 = 128 << SCALEBITS;
+// FIX(x) from jccolor.c
+pub(crate) const FIX_0_29900: i32 = 19595;
+pub(crate) const FIX_0_58700: i32 = 38470;
+pub(crate) const FIX_0_11400: i32 = 7471;
+pub(crate) const FIX_0_16874: i32 = 11059;
+pub(crate) const FIX_0_33126: i32 = 21709;
+pub(crate) const FIX_0_50000: i32 = 32768;
+pub(crate) const FIX_0_41869: i32 = 27439;
+pub(crate) const FIX_0_08131: i32 = 5329;
+
+/// Byte offsets of R, G, B and the pixel size, like `RGB_RED` etc. in `jmorecfg.h`
+fn rgb_layout(color_space: J_COLOR_SPACE) -> ([usize; 3], usize) {
+    match color_space {
+        JCS_EXT_RGBX | JCS_EXT_RGBA => ([0, 1, 2], 4),
+        JCS_EXT_BGR => ([2, 1, 0], 3),
+        JCS_EXT_BGRX | JCS_EXT_BGRA => ([2, 1, 0], 4),
+        JCS_EXT_XBGR | JCS_EXT_ABGR => ([3, 2, 1], 4),
+        JCS_EXT_XRGB | JCS_EXT_ARGB => ([1, 2, 3], 4),
+        _ => ([0, 1, 2], 3),
+    }
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_rgb_ycc_convert")]
+unsafe extern "C" fn jsimd_rgb_ycc_convert(cinfo: &mut jpeg_compress_struct, input_buf: JSAMPARRAY, output_buf: JSAMPIMAGE, output_row: JDIMENSION, num_rows: c_int) {
+    let (offsets, pixel_size) = rgb_layout(cinfo.in_color_space);
+    let width = cinfo.image_width as usize;
+    for row in 0..num_rows.max(0) as usize {
+        let input = std::slice::from_raw_parts(*input_buf.add(row), width * pixel_size);
+        let out_row = output_row as usize + row;
+        let outputs = [0, 1, 2].map(|c| (*(*output_buf.add(c)).add(out_row)).cast_mut());
+        backend::rgb_ycc_row(input, outputs, width, offsets, pixel_size);
+    }
+}
+
+/// Converts pixels from `start` to the end of the row one at a time
+pub(crate) unsafe fn rgb_ycc_tail(input: &[u8], outputs: [*mut u8; 3], start: usize, width: usize, offsets: [usize; 3], pixel_size: usize) {
+    for col in start..width {
+        let pixel = &input[col * pixel_size..];
+        let [r, g, b] = offsets.map(|o| i32::from(pixel[o]));
+        *outputs[0].add(col) = ((FIX_0_29900 * r + FIX_0_58700 * g + FIX_0_11400 * b + ONE_HALF) >> SCALEBITS) as u8;
+        *outputs[1].add(col) = ((-FIX_0_16874 * r - FIX_0_33126 * g + FIX_0_50000 * b + CBCR_OFFSET + ONE_HALF - 1) >> SCALEBITS) as u8;
+        *outputs[2].add(col) = ((FIX_0_50000 * r - FIX_0_41869 * g - FIX_0_08131 * b + CBCR_OFFSET + ONE_HALF - 1) >> SCALEBITS) as u8;
+    }
+}
+
+/// Replicates the last column, like `expand_right_edge` in `jcsample.c`
+unsafe fn expand_right_edge(image_data: JSAMPARRAY, num_rows: c_int, input_cols: usize, output_cols: usize) {
+    if output_cols <= input_cols || input_cols == 0 {
+        return;
+    }
+    for row in 0..num_rows.max(0) as usize {
+        let ptr = (*image_data.add(row)).cast_mut();
+        let pixval = *ptr.add(input_cols - 1);
+        ptr.add(input_cols).write_bytes(pixval, output_cols - input_cols);
+    }
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_h2v1_downsample")]
+unsafe extern "C" fn jsimd_h2v1_downsample(cinfo: &mut jpeg_compress_struct, compptr: &mut jpeg_component_info, input_data: JSAMPARRAY, output_data: JSAMPARRAY) {
+    let output_cols = compptr.width_in_blocks as usize * DCTSIZE;
+    expand_right_edge(input_data, cinfo.max_v_samp_factor, cinfo.image_width as usize, output_cols * 2);
+    for row in 0..compptr.v_samp_factor.max(0) as usize {
+        backend::h2v1_downsample_row(*input_data.add(row), (*output_data.add(row)).cast_mut(), output_cols);
+    }
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_h2v2_downsample")]
+unsafe extern "C" fn jsimd_h2v2_downsample(cinfo: &mut jpeg_compress_struct, compptr: &mut jpeg_component_info, input_data: JSAMPARRAY, output_data: JSAMPARRAY) {
+    let output_cols = compptr.width_in_blocks as usize * DCTSIZE;
+    expand_right_edge(input_data, cinfo.max_v_samp_factor, cinfo.image_width as usize, output_cols * 2);
+    for row in 0..compptr.v_samp_factor.max(0) as usize {
+        backend::h2v2_downsample_row(*input_data.add(row * 2), *input_data.add(row * 2 + 1), (*output_data.add(row)).cast_mut(), output_cols);
+    }
+}
+
+pub(crate) const CONST_BITS: u32 = 13;
+pub(crate) const PASS1_BITS: u32 = 2;
+// FIX(x) from jfdctint.c
+pub(crate) const FIX_0_298631336: i32 = 2446;
+pub(crate) const FIX_0_390180644: i32 = 3196;
+pub(crate) const FIX_0_541196100: i32 = 4433;
+pub(crate) const FIX_0_765366865: i32 = 6270;
+pub(crate) const FIX_0_899976223: i32 = 7373;
+pub(crate) const FIX_1_175875602: i32 = 9633;
+pub(crate) const FIX_1_501321110: i32 = 12299;
+pub(crate) const FIX_1_847759065: i32 = 15137;
+pub(crate) const FIX_1_961570560: i32 = 16069;
+pub(crate) const FIX_2_053119869: i32 = 16819;
+pub(crate) const FIX_2_562915447: i32 = 20995;
+pub(crate) const FIX_3_072711026: i32 = 25172;
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_fdct_islow")]
+unsafe extern "C" fn jsimd_fdct_islow(data: *mut DCTELEM) {
+    backend::fdct_islow(data);
+}
+
+// FIX(x) from jfdctfst.c, with 8 fractional bits
+pub(crate) const FIX_0_382683433: i32 = 98;
+pub(crate) const FIX_0_541196100_FAST: i32 = 139;
+pub(crate) const FIX_0_707106781: i32 = 181;
+pub(crate) const FIX_1_306562965: i32 = 334;
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_fdct_ifast")]
+unsafe extern "C" fn jsimd_fdct_ifast(data: *mut DCTELEM) {
+    backend::fdct_ifast(data);
+}
+
+#[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+#[cfg_attr(feature = "prefix_symbols", export_name = "mozjpeg_jsimd_quantize")]
+unsafe extern "C" fn jsimd_quantize(coef_block: *mut JCOEF, divisors: *mut DCTELEM, workspace: *mut DCTELEM) {
+    backend::quantize(coef_block, divisors, workspace);
+}
+
+/// Declares kernels without a Rust version. Their `jsimd_can_*` returns 0, so libjpeg never calls them.
+macro_rules! unsupported {
+    ($($can:ident),* ; $($name:ident($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        $(
+            #[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+            #[cfg_attr(feature = "prefix_symbols", export_name = concat!("mozjpeg_", stringify!($can)))]
+            extern "C" fn $can() -> c_int {
+                0
+            }
+        )*
+        $(
+            #[cfg_attr(not(feature = "prefix_symbols"), no_mangle)]
+            #[cfg_attr(feature = "prefix_symbols", export_name = concat!("mozjpeg_", stringify!($name)))]
+            extern "C" fn $name($(_: $arg),*) $(-> $ret)? {
+                unreachable!()
+            }
+        )*
+    };
+}
+
+unsupported! {
+    jsimd_can_rgb_gray, jsimd_can_ycc_rgb, jsimd_can_ycc_rgb565, jsimd_c_can_null_convert,
+    jsimd_can_h2v2_smooth_downsample, jsimd_can_h2v2_upsample, jsimd_can_h2v1_upsample, jsimd_can_int_upsample,
+    jsimd_can_h2v2_fancy_upsample, jsimd_can_h2v1_fancy_upsample, jsimd_can_h1v2_fancy_upsample,
+    jsimd_can_h2v2_merged_upsample, jsimd_can_h2v1_merged_upsample, jsimd_can_convsamp, jsimd_can_convsamp_float,
+    jsimd_can_fdct_float, jsimd_can_quantize_float, jsimd_can_idct_2x2, jsimd_can_idct_4x4, jsimd_can_idct_6x6,
+    jsimd_can_idct_12x12, jsimd_can_idct_islow, jsimd_can_idct_ifast, jsimd_can_idct_float,
+    jsimd_can_huff_encode_one_block, jsimd_can_encode_mcu_AC_first_prepare, jsimd_can_encode_mcu_AC_refine_prepare;
+
+    jsimd_rgb_gray_convert(*mut c_void, JSAMPARRAY, JSAMPIMAGE, JDIMENSION, c_int);
+    jsimd_ycc_rgb_convert(*mut c_void, JSAMPIMAGE, JDIMENSION, JSAMPARRAY, c_int);
+    jsimd_ycc_rgb565_convert(*mut c_void, JSAMPIMAGE, JDIMENSION, JSAMPARRAY, c_int);
+    jsimd_c_null_convert(*mut c_void, JSAMPARRAY, JSAMPIMAGE, JDIMENSION, c_int);
+    jsimd_h2v2_smooth_downsample(*mut c_void, *mut c_void, JSAMPARRAY, JSAMPARRAY);
+    jsimd_h2v2_upsample(*mut c_void, *mut c_void, JSAMPARRAY, *mut JSAMPARRAY);
+    jsimd_h2v1_upsample(*mut c_void, *mut c_void, JSAMPARRAY, *mut JSAMPARRAY);
+    jsimd_int_upsample(*mut c_void, *mut c_void, JSAMPARRAY, *mut JSAMPARRAY);
+    jsimd_h2v2_fancy_upsample(*mut c_void, *mut c_void, JSAMPARRAY, *mut JSAMPARRAY);
+    jsimd_h2v1_fancy_upsample(*mut c_void, *mut c_void, JSAMPARRAY, *mut JSAMPARRAY);
+    jsimd_h1v2_fancy_upsample(*mut c_void, *mut c_void, JSAMPARRAY, *mut JSAMPARRAY);
+    jsimd_h2v2_merged_upsample(*mut c_void, JSAMPIMAGE, JDIMENSION, JSAMPARRAY);
+    jsimd_h2v1_merged_upsample(*mut c_void, JSAMPIMAGE, JDIMENSION, JSAMPARRAY);
+    jsimd_convsamp(JSAMPARRAY, JDIMENSION, *mut DCTELEM);
+    jsimd_convsamp_float(JSAMPARRAY, JDIMENSION, *mut f32);
+    jsimd_fdct_float(*mut f32);
+    jsimd_quantize_float(*mut JCOEF, *mut f32, *mut f32);
+    jsimd_idct_2x2(*mut c_void, *mut c_void, *mut JCOEF, JSAMPARRAY, JDIMENSION);
+    jsimd_idct_4x4(*mut c_void, *mut c_void, *mut JCOEF, JSAMPARRAY, JDIMENSION);
+    jsimd_idct_6x6(*mut c_void, *mut c_void, *mut JCOEF, JSAMPARRAY, JDIMENSION);
+    jsimd_idct_12x12(*mut c_void, *mut c_void, *mut JCOEF, JSAMPARRAY, JDIMENSION);
+    jsimd_idct_islow(*mut c_void, *mut c_void, *mut JCOEF, JSAMPARRAY, JDIMENSION);
+    jsimd_idct_ifast(*mut c_void, *mut c_void, *mut JCOEF, JSAMPARRAY, JDIMENSION);
+    jsimd_idct_float(*mut c_void, *mut c_void, *mut JCOEF, JSAMPARRAY, JDIMENSION);
+    jsimd_huff_encode_one_block(*mut c_void, *mut u8, *mut JCOEF, c_int, *mut c_void, *mut c_void) -> *mut u8;
+    jsimd_encode_mcu_AC_first_prepare(*const JCOEF, *const c_int, c_int, c_int, *mut u16, *mut usize);
+    jsimd_encode_mcu_AC_refine_prepare(*const JCOEF, *const c_int, c_int, c_int, *mut u16, *mut usize) -> c_int;
+}
@@ -0,0 +1,273 @@
+//! WebAssembly SIMD128 backend of `jsimd_rust.rs`, with the `wasm_simd` feature
+use crate::jsimd_rust::*;
+use crate::*;
+use std::arch::wasm32::*;
+
+#[target_feature(enable = "simd128")]
+pub(crate) unsafe fn rgb_ycc_row(input: &[u8], outputs: [*mut u8; 3], width: usize, offsets: [usize; 3], pixel_size: usize) {
+    // Swizzle indices gathering one channel of 16 pixels from each of the 3 or 4 input vectors.
+    // Out-of-range indices (0x80) give zeros, so the partial results can be OR-ed together.
+    let mut indices = [[[0x80u8; 16]; 4]; 3];
+    for (channel, &offset) in indices.iter_mut().zip(&offsets) {
+        for (pixel, byte) in (offset..).step_by(pixel_size).take(16).enumerate() {
+            channel[byte / 16][pixel] = (byte % 16) as u8;
+        }
+    }
+
+    let chunks = width / 16;
+    for chunk in 0..chunks {
+        let src = input.as_ptr().add(chunk * 16 * pixel_size);
+        let vectors = [0, 1, 2, 3].map(|v| if v < pixel_size { v128_load(src.add(v * 16).cast()) } else { u8x16_splat(0) });
+        let [r, g, b] = [0, 1, 2].map(|c| {
+            let mut channel = u8x16_splat(0);
+            for (vector, index) in vectors.iter().zip(&indices[c]) {
+                channel = v128_or(channel, u8x16_swizzle(*vector, v128_load(index.as_ptr().cast())));
+            }
+            channel
+        });
+        let y = color_16(r, g, b, [FIX_0_29900, FIX_0_58700, FIX_0_11400], ONE_HALF);
+        let cb = color_16(r, g, b, [-FIX_0_16874, -FIX_0_33126, FIX_0_50000], CBCR_OFFSET + ONE_HALF - 1);
+        let cr = color_16(r, g, b, [FIX_0_50000, -FIX_0_41869, -FIX_0_08131], CBCR_OFFSET + ONE_HALF - 1);
+        for (out, res) in outputs.iter().zip([y, cb, cr]) {
+            v128_store(out.add(chunk * 16).cast(), res);
+        }
+    }
+
+    rgb_ycc_tail(input, outputs, chunks * 16, width, offsets, pixel_size);
+}
+
+/// `(r * c0 + g * c1 + b * c2 + bias) >> SCALEBITS` for 16 pixels
+#[target_feature(enable = "simd128")]
+unsafe fn color_16(r: v128, g: v128, b: v128, coefs: [i32; 3], bias: i32) -> v128 {
+    let [c0, c1, c2] = coefs.map(|c| i32x4_splat(c));
+    let bias = i32x4_splat(bias);
+    let quarter = |r: v128, g: v128, b: v128| {
+        let sum = i32x4_add(i32x4_add(i32x4_mul(r, c0), i32x4_mul(g, c1)), i32x4_add(i32x4_mul(b, c2), bias));
+        i32x4_shr(sum, SCALEBITS)
+    };
+    let half = |r: v128, g: v128, b: v128| {
+        let lo = quarter(u32x4_extend_low_u16x8(r), u32x4_extend_low_u16x8(g), u32x4_extend_low_u16x8(b));
+        let hi = quarter(u32x4_extend_high_u16x8(r), u32x4_extend_high_u16x8(g), u32x4_extend_high_u16x8(b));
+        i16x8_narrow_i32x4(lo, hi)
+    };
+    let lo = half(u16x8_extend_low_u8x16(r), u16x8_extend_low_u8x16(g), u16x8_extend_low_u8x16(b));
+    let hi = half(u16x8_extend_high_u8x16(r), u16x8_extend_high_u8x16(g), u16x8_extend_high_u8x16(b));
+    u8x16_narrow_i16x8(lo, hi)
+}
+
+/// `output_cols` is a multiple of `DCTSIZE`
+#[target_feature(enable = "simd128")]
+pub(crate) unsafe fn h2v1_downsample_row(input: *const u8, output: *mut u8, output_cols: usize) {
+    // bias = 0,1,0,1,… for successive samples
+    let bias = u16x8(0, 1, 0, 1, 0, 1, 0, 1);
+    for col in (0..output_cols).step_by(8) {
+        let sums = u16x8_extadd_pairwise_u8x16(v128_load(input.add(col * 2).cast()));
+        let avg = u16x8_shr(i16x8_add(sums, bias), 1);
+        v128_store64_lane::<0>(u8x16_narrow_i16x8(avg, avg), output.add(col).cast());
+    }
+}
+
+#[target_feature(enable = "simd128")]
+pub(crate) unsafe fn h2v2_downsample_row(input0: *const u8, input1: *const u8, output: *mut u8, output_cols: usize) {
+    // bias = 1,2,1,2,… for successive samples
+    let bias = u16x8(1, 2, 1, 2, 1, 2, 1, 2);
+    for col in (0..output_cols).step_by(8) {
+        let sums0 = u16x8_extadd_pairwise_u8x16(v128_load(input0.add(col * 2).cast()));
+        let sums1 = u16x8_extadd_pairwise_u8x16(v128_load(input1.add(col * 2).cast()));
+        let avg = u16x8_shr(i16x8_add(i16x8_add(sums0, sums1), bias), 2);
+        v128_store64_lane::<0>(u8x16_narrow_i16x8(avg, avg), output.add(col).cast());
+    }
+}
+
+/// Transposes 8×8 16-bit elements
+#[target_feature(enable = "simd128")]
+unsafe fn transpose(r: [v128; 8]) -> [v128; 8] {
+    let a = [
+        i16x8_shuffle::<0, 8, 1, 9, 2, 10, 3, 11>(r[0], r[1]),
+        i16x8_shuffle::<4, 12, 5, 13, 6, 14, 7, 15>(r[0], r[1]),
+        i16x8_shuffle::<0, 8, 1, 9, 2, 10, 3, 11>(r[2], r[3]),
+        i16x8_shuffle::<4, 12, 5, 13, 6, 14, 7, 15>(r[2], r[3]),
+        i16x8_shuffle::<0, 8, 1, 9, 2, 10, 3, 11>(r[4], r[5]),
+        i16x8_shuffle::<4, 12, 5, 13, 6, 14, 7, 15>(r[4], r[5]),
+        i16x8_shuffle::<0, 8, 1, 9, 2, 10, 3, 11>(r[6], r[7]),
+        i16x8_shuffle::<4, 12, 5, 13, 6, 14, 7, 15>(r[6], r[7]),
+    ];
+    let b = [
+        i32x4_shuffle::<0, 4, 1, 5>(a[0], a[2]),
+        i32x4_shuffle::<2, 6, 3, 7>(a[0], a[2]),
+        i32x4_shuffle::<0, 4, 1, 5>(a[1], a[3]),
+        i32x4_shuffle::<2, 6, 3, 7>(a[1], a[3]),
+        i32x4_shuffle::<0, 4, 1, 5>(a[4], a[6]),
+        i32x4_shuffle::<2, 6, 3, 7>(a[4], a[6]),
+        i32x4_shuffle::<0, 4, 1, 5>(a[5], a[7]),
+        i32x4_shuffle::<2, 6, 3, 7>(a[5], a[7]),
+    ];
+    [
+        i64x2_shuffle::<0, 2>(b[0], b[4]),
+        i64x2_shuffle::<1, 3>(b[0], b[4]),
+        i64x2_shuffle::<0, 2>(b[1], b[5]),
+        i64x2_shuffle::<1, 3>(b[1], b[5]),
+        i64x2_shuffle::<0, 2>(b[2], b[6]),
+        i64x2_shuffle::<1, 3>(b[2], b[6]),
+        i64x2_shuffle::<0, 2>(b[3], b[7]),
+        i64x2_shuffle::<1, 3>(b[3], b[7]),
+    ]
+}
+
+unsafe fn load_block(data: *const DCTELEM) -> [v128; 8] {
+    [0, 1, 2, 3, 4, 5, 6, 7].map(|row| v128_load(data.add(row * DCTSIZE).cast()))
+}
+
+unsafe fn store_block(data: *mut DCTELEM, rows: [v128; 8]) {
+    for (row, v) in rows.into_iter().enumerate() {
+        v128_store(data.add(row * DCTSIZE).cast(), v);
+    }
+}
+
+/// Both passes work on vectors of the same coefficient of 8 rows (or columns), so the block is transposed around pass 1
+#[target_feature(enable = "simd128")]
+pub(crate) unsafe fn fdct_islow(data: *mut DCTELEM) {
+    let columns = islow_pass(transpose(load_block(data)), true);
+    store_block(data, islow_pass(transpose(columns), false));
+}
+
+#[target_feature(enable = "simd128")]
+unsafe fn islow_pass(d: [v128; 8], pass1: bool) -> [v128; 8] {
+    let lo = islow_pass_half(d.map(|v| i32x4_extend_low_i16x8(v)), pass1);
+    let hi = islow_pass_half(d.map(|v| i32x4_extend_high_i16x8(v)), pass1);
+    [0, 1, 2, 3, 4, 5, 6, 7].map(|i| i16x8_narrow_i32x4(lo[i], hi[i]))
+}
+
+/// One pass of `jpeg_fdct_islow` on 32-bit lanes
+#[target_feature(enable = "simd128")]
+unsafe fn islow_pass_half(d: [v128; 8], pass1: bool) -> [v128; 8] {
+    let mul = |v: v128, c: i32| i32x4_mul(v, i32x4_splat(c));
+    let descale = |v: v128, n: u32| i32x4_shr(i32x4_add(v, i32x4_splat(1 << (n - 1))), n);
+    let bits = if pass1 { CONST_BITS - PASS1_BITS } else { CONST_BITS + PASS1_BITS };
+
+    let tmp0 = i32x4_add(d[0], d[7]);
+    let tmp7 = i32x4_sub(d[0], d[7]);
+    let tmp1 = i32x4_add(d[1], d[6]);
+    let tmp6 = i32x4_sub(d[1], d[6]);
+    let tmp2 = i32x4_add(d[2], d[5]);
+    let tmp5 = i32x4_sub(d[2], d[5]);
+    let tmp3 = i32x4_add(d[3], d[4]);
+    let tmp4 = i32x4_sub(d[3], d[4]);
+
+    let tmp10 = i32x4_add(tmp0, tmp3);
+    let tmp13 = i32x4_sub(tmp0, tmp3);
+    let tmp11 = i32x4_add(tmp1, tmp2);
+    let tmp12 = i32x4_sub(tmp1, tmp2);
+
+    let mut out = [i32x4_splat(0); 8];
+    if pass1 {
+        out[0] = i32x4_shl(i32x4_add(tmp10, tmp11), PASS1_BITS);
+        out[4] = i32x4_shl(i32x4_sub(tmp10, tmp11), PASS1_BITS);
+    } else {
+        out[0] = descale(i32x4_add(tmp10, tmp11), PASS1_BITS);
+        out[4] = descale(i32x4_sub(tmp10, tmp11), PASS1_BITS);
+    }
+
+    let z1 = mul(i32x4_add(tmp12, tmp13), FIX_0_541196100);
+    out[2] = descale(i32x4_add(z1, mul(tmp13, FIX_0_765366865)), bits);
+    out[6] = descale(i32x4_add(z1, mul(tmp12, -FIX_1_847759065)), bits);
+
+    let z1 = i32x4_add(tmp4, tmp7);
+    let z2 = i32x4_add(tmp5, tmp6);
+    let z3 = i32x4_add(tmp4, tmp6);
+    let z4 = i32x4_add(tmp5, tmp7);
+    let z5 = mul(i32x4_add(z3, z4), FIX_1_175875602);
+
+    let tmp4 = mul(tmp4, FIX_0_298631336);
+    let tmp5 = mul(tmp5, FIX_2_053119869);
+    let tmp6 = mul(tmp6, FIX_3_072711026);
+    let tmp7 = mul(tmp7, FIX_1_501321110);
+    let z1 = mul(z1, -FIX_0_899976223);
+    let z2 = mul(z2, -FIX_2_562915447);
+    let z3 = i32x4_add(mul(z3, -FIX_1_961570560), z5);
+    let z4 = i32x4_add(mul(z4, -FIX_0_390180644), z5);
+
+    out[7] = descale(i32x4_add(i32x4_add(tmp4, z1), z3), bits);
+    out[5] = descale(i32x4_add(i32x4_add(tmp5, z2), z4), bits);
+    out[3] = descale(i32x4_add(i32x4_add(tmp6, z2), z3), bits);
+    out[1] = descale(i32x4_add(i32x4_add(tmp7, z1), z4), bits);
+    out
+}
+
+#[target_feature(enable = "simd128")]
+pub(crate) unsafe fn fdct_ifast(data: *mut DCTELEM) {
+    let columns = ifast_pass(transpose(load_block(data)));
+    store_block(data, ifast_pass(transpose(columns)));
+}
+
+/// One pass of `jpeg_fdct_ifast`, which is the same for rows and columns
+#[target_feature(enable = "simd128")]
+unsafe fn ifast_pass(d: [v128; 8]) -> [v128; 8] {
+    // `MULTIPLY` truncates, because `USE_ACCURATE_ROUNDING` isn't defined
+    let mul = |v: v128, c: i32| {
+        let c = i16x8_splat(c as i16);
+        let lo = i32x4_shr(i32x4_extmul_low_i16x8(v, c), 8);
+        let hi = i32x4_shr(i32x4_extmul_high_i16x8(v, c), 8);
+        i16x8_narrow_i32x4(lo, hi)
+    };
+
+    let tmp0 = i16x8_add(d[0], d[7]);
+    let tmp7 = i16x8_sub(d[0], d[7]);
+    let tmp1 = i16x8_add(d[1], d[6]);
+    let tmp6 = i16x8_sub(d[1], d[6]);
+    let tmp2 = i16x8_add(d[2], d[5]);
+    let tmp5 = i16x8_sub(d[2], d[5]);
+    let tmp3 = i16x8_add(d[3], d[4]);
+    let tmp4 = i16x8_sub(d[3], d[4]);
+
+    let tmp10 = i16x8_add(tmp0, tmp3);
+    let tmp13 = i16x8_sub(tmp0, tmp3);
+    let tmp11 = i16x8_add(tmp1, tmp2);
+    let tmp12 = i16x8_sub(tmp1, tmp2);
+
+    let mut out = [i16x8_splat(0); 8];
+    out[0] = i16x8_add(tmp10, tmp11);
+    out[4] = i16x8_sub(tmp10, tmp11);
+
+    let z1 = mul(i16x8_add(tmp12, tmp13), FIX_0_707106781);
+    out[2] = i16x8_add(tmp13, z1);
+    out[6] = i16x8_sub(tmp13, z1);
+
+    let tmp10 = i16x8_add(tmp4, tmp5);
+    let tmp11 = i16x8_add(tmp5, tmp6);
+    let tmp12 = i16x8_add(tmp6, tmp7);
+
+    let z5 = mul(i16x8_sub(tmp10, tmp12), FIX_0_382683433);
+    let z2 = i16x8_add(mul(tmp10, FIX_0_541196100_FAST), z5);
+    let z4 = i16x8_add(mul(tmp12, FIX_1_306562965), z5);
+    let z3 = mul(tmp11, FIX_0_707106781);
+
+    let z11 = i16x8_add(tmp7, z3);
+    let z13 = i16x8_sub(tmp7, z3);
+
+    out[5] = i16x8_add(z13, z2);
+    out[3] = i16x8_sub(z13, z2);
+    out[1] = i16x8_add(z11, z4);
+    out[7] = i16x8_sub(z11, z4);
+    out
+}
+
+/// Like the x86 version, this uses the `scale` row of `divisors` instead of a per-coefficient shift.
+/// `jcdctmgr.c` only uses it when `shift` is positive for all coefficients.
+#[target_feature(enable = "simd128")]
+pub(crate) unsafe fn quantize(coef_block: *mut JCOEF, divisors: *const DCTELEM, workspace: *const DCTELEM) {
+    // high halves of 32-bit products
+    let high = |lo: v128, hi: v128| i16x8_shuffle::<1, 3, 5, 7, 9, 11, 13, 15>(lo, hi);
+    for i in (0..DCTSIZE2).step_by(8) {
+        let coefs = v128_load(workspace.add(i).cast());
+        let recip = v128_load(divisors.add(i).cast());
+        let corr = v128_load(divisors.add(i + DCTSIZE2).cast());
+        let scale = v128_load(divisors.add(i + DCTSIZE2 * 2).cast());
+
+        let sign = i16x8_shr(coefs, 15);
+        let abs = i16x8_add(i16x8_abs(coefs), corr);
+        let product = high(u32x4_extmul_low_u16x8(abs, recip), u32x4_extmul_high_u16x8(abs, recip));
+        let product = high(u32x4_extmul_low_u16x8(product, scale), u32x4_extmul_high_u16x8(product, scale));
+        v128_store(coef_block.add(i).cast(), i16x8_sub(v128_xor(product, sign), sign));
+    }
+}
@@ -0,0 +1,458 @@
+//! x86-64 backend of `jsimd_rust.rs`, with the `x86_intrinsics_simd` feature. Doesn't need `nasm`.
+//!
+//! SSE2 is always available on x86-64. Color conversion, downsampling, `fdct_islow` and quantization
+//! also have AVX2 versions (like in libjpeg-turbo), which are picked at run time.
+use crate::jsimd_rust::*;
+use crate::simd::{rust_instruction_set, InstructionSet};
+use crate::*;
+use std::arch::x86_64::*;
+
+fn avx2() -> bool {
+    rust_instruction_set() == InstructionSet::Avx2
+}
+
+pub(crate) unsafe fn rgb_ycc_row(input: &[u8], outputs: [*mut u8; 3], width: usize, offsets: [usize; 3], pixel_size: usize) {
+    let done = if avx2() {
+        rgb_ycc_row_avx2(input, outputs, width, offsets, pixel_size)
+    } else {
+        rgb_ycc_row_sse2(input, outputs, width, offsets, pixel_size)
+    };
+    rgb_ycc_tail(input, outputs, done, width, offsets, pixel_size);
+}
+
+/// Returns number of pixels converted
+unsafe fn rgb_ycc_row_sse2(input: &[u8], outputs: [*mut u8; 3], width: usize, offsets: [usize; 3], pixel_size: usize) -> usize {
+    let chunks = width / 16;
+    for chunk in 0..chunks {
+        let pixels = &input[chunk * 16 * pixel_size..][..16 * pixel_size];
+        // SSE2 has no byte shuffle
+        let [r, g, b] = offsets.map(|o| {
+            let channel: [u8; 16] = std::array::from_fn(|i| pixels[i * pixel_size + o]);
+            _mm_loadu_si128(channel.as_ptr().cast())
+        });
+        let y = color_16_sse2(r, g, b, [FIX_0_29900, FIX_0_58700, FIX_0_11400], ONE_HALF);
+        let cb = color_16_sse2(r, g, b, [-FIX_0_16874, -FIX_0_33126, FIX_0_50000], CBCR_OFFSET + ONE_HALF - 1);
+        let cr = color_16_sse2(r, g, b, [FIX_0_50000, -FIX_0_41869, -FIX_0_08131], CBCR_OFFSET + ONE_HALF - 1);
+        for (out, res) in outputs.iter().zip([y, cb, cr]) {
+            _mm_storeu_si128(out.add(chunk * 16).cast(), res);
+        }
+    }
+    chunks * 16
+}
+
+/// `(r * c0 + g * c1 + b * c2 + bias) >> SCALEBITS` for 16 pixels
+unsafe fn color_16_sse2(r: __m128i, g: __m128i, b: __m128i, coefs: [i32; 3], bias: i32) -> __m128i {
+    let zero = _mm_setzero_si128();
+    let half = |unpack: unsafe fn(__m128i, __m128i) -> __m128i| {
+        let [r, g, b] = [r, g, b].map(|v| unpack(v, zero));
+        let (lo, hi) = [(r, coefs[0]), (g, coefs[1]), (b, coefs[2])].into_iter().fold(
+            (_mm_set1_epi32(bias), _mm_set1_epi32(bias)),
+            |(lo, hi), (v, c)| {
+                // 16×16-bit unsigned multiply gives 32-bit products in two halves
+                let c16 = _mm_set1_epi16(c.unsigned_abs() as u16 as i16);
+                let (prod_lo, prod_hi) = (_mm_mullo_epi16(v, c16), _mm_mulhi_epu16(v, c16));
+                let (p0, p1) = (_mm_unpacklo_epi16(prod_lo, prod_hi), _mm_unpackhi_epi16(prod_lo, prod_hi));
+                if c < 0 {
+                    (_mm_sub_epi32(lo, p0), _mm_sub_epi32(hi, p1))
+                } else {
+                    (_mm_add_epi32(lo, p0), _mm_add_epi32(hi, p1))
+                }
+            });
+        _mm_packs_epi32(_mm_srai_epi32(lo, SCALEBITS as i32), _mm_srai_epi32(hi, SCALEBITS as i32))
+    };
+    _mm_packus_epi16(half(|a, b| _mm_unpacklo_epi8(a, b)), half(|a, b| _mm_unpackhi_epi8(a, b)))
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn rgb_ycc_row_avx2(input: &[u8], outputs: [*mut u8; 3], width: usize, offsets: [usize; 3], pixel_size: usize) -> usize {
+    // Shuffle indices gathering one channel of 16 pixels from each of the 3 or 4 input vectors.
+    // Indices with the high bit set give zeros, so the partial results can be OR-ed together.
+    let mut indices = [[[0x80u8; 16]; 4]; 3];
+    for (channel, &offset) in indices.iter_mut().zip(&offsets) {
+        for (pixel, byte) in (offset..).step_by(pixel_size).take(16).enumerate() {
+            channel[byte / 16][pixel] = (byte % 16) as u8;
+        }
+    }
+
+    let chunks = width / 16;
+    for chunk in 0..chunks {
+        let src = input.as_ptr().add(chunk * 16 * pixel_size);
+        let vectors = [0, 1, 2, 3].map(|v| if v < pixel_size { _mm_loadu_si128(src.add(v * 16).cast()) } else { _mm_setzero_si128() });
+        let [r, g, b] = [0, 1, 2].map(|c| {
+            let mut channel = _mm_setzero_si128();
+            for (vector, index) in vectors.iter().zip(&indices[c]) {
+                channel = _mm_or_si128(channel, _mm_shuffle_epi8(*vector, _mm_loadu_si128(index.as_ptr().cast())));
+            }
+            channel
+        });
+        let y = color_16_avx2(r, g, b, [FIX_0_29900, FIX_0_58700, FIX_0_11400], ONE_HALF);
+        let cb = color_16_avx2(r, g, b, [-FIX_0_16874, -FIX_0_33126, FIX_0_50000], CBCR_OFFSET + ONE_HALF - 1);
+        let cr = color_16_avx2(r, g, b, [FIX_0_50000, -FIX_0_41869, -FIX_0_08131], CBCR_OFFSET + ONE_HALF - 1);
+        for (out, res) in outputs.iter().zip([y, cb, cr]) {
+            _mm_storeu_si128(out.add(chunk * 16).cast(), res);
+        }
+    }
+    chunks * 16
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn color_16_avx2(r: __m128i, g: __m128i, b: __m128i, coefs: [i32; 3], bias: i32) -> __m128i {
+    let [c0, c1, c2] = coefs.map(|c| _mm256_set1_epi32(c));
+    let bias = _mm256_set1_epi32(bias);
+    let half = |r: __m128i, g: __m128i, b: __m128i| {
+        let [r, g, b] = [r, g, b].map(|v| _mm256_cvtepu8_epi32(v));
+        let sum = _mm256_add_epi32(_mm256_add_epi32(_mm256_mullo_epi32(r, c0), _mm256_mullo_epi32(g, c1)), _mm256_add_epi32(_mm256_mullo_epi32(b, c2), bias));
+        let sum = _mm256_srai_epi32(sum, SCALEBITS as i32);
+        _mm_packs_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256(sum, 1))
+    };
+    let lo = half(r, g, b);
+    let hi = half(_mm_srli_si128(r, 8), _mm_srli_si128(g, 8), _mm_srli_si128(b, 8));
+    _mm_packus_epi16(lo, hi)
+}
+
+/// `output_cols` is a multiple of `DCTSIZE`
+pub(crate) unsafe fn h2v1_downsample_row(input: *const u8, output: *mut u8, output_cols: usize) {
+    let done = if avx2() { h2v1_downsample_avx2(input, output, output_cols) } else { 0 };
+    // bias = 0,1,0,1,… for successive samples
+    let bias = _mm_set1_epi32(1 << 16);
+    let mask = _mm_set1_epi16(0xFF);
+    for col in (done..output_cols).step_by(8) {
+        let pixels = _mm_loadu_si128(input.add(col * 2).cast());
+        let sums = _mm_add_epi16(_mm_and_si128(pixels, mask), _mm_srli_epi16(pixels, 8));
+        let avg = _mm_srli_epi16(_mm_add_epi16(sums, bias), 1);
+        _mm_storel_epi64(output.add(col).cast(), _mm_packus_epi16(avg, avg));
+    }
+}
+
+/// Returns number of columns done, a multiple of 16
+#[target_feature(enable = "avx2")]
+unsafe fn h2v1_downsample_avx2(input: *const u8, output: *mut u8, output_cols: usize) -> usize {
+    let bias = _mm256_set1_epi32(1 << 16);
+    let mask = _mm256_set1_epi16(0xFF);
+    let chunks = output_cols / 16;
+    for col in (0..chunks * 16).step_by(16) {
+        let pixels = _mm256_loadu_si256(input.add(col * 2).cast());
+        let sums = _mm256_add_epi16(_mm256_and_si256(pixels, mask), _mm256_srli_epi16(pixels, 8));
+        let avg = _mm256_srli_epi16(_mm256_add_epi16(sums, bias), 1);
+        store_packed_avx2(output.add(col), avg);
+    }
+    chunks * 16
+}
+
+/// Stores 16 16-bit values as bytes
+#[target_feature(enable = "avx2")]
+unsafe fn store_packed_avx2(output: *mut u8, v: __m256i) {
+    let packed = _mm_packus_epi16(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1));
+    _mm_storeu_si128(output.cast(), packed);
+}
+
+pub(crate) unsafe fn h2v2_downsample_row(input0: *const u8, input1: *const u8, output: *mut u8, output_cols: usize) {
+    let done = if avx2() { h2v2_downsample_avx2(input0, input1, output, output_cols) } else { 0 };
+    // bias = 1,2,1,2,… for successive samples
+    let bias = _mm_set1_epi32(1 | (2 << 16));
+    let mask = _mm_set1_epi16(0xFF);
+    for col in (done..output_cols).step_by(8) {
+        let p0 = _mm_loadu_si128(input0.add(col * 2).cast());
+        let p1 = _mm_loadu_si128(input1.add(col * 2).cast());
+        let sums0 = _mm_add_epi16(_mm_and_si128(p0, mask), _mm_srli_epi16(p0, 8));
+        let sums1 = _mm_add_epi16(_mm_and_si128(p1, mask), _mm_srli_epi16(p1, 8));
+        let avg = _mm_srli_epi16(_mm_add_epi16(_mm_add_epi16(sums0, sums1), bias), 2);
+        _mm_storel_epi64(output.add(col).cast(), _mm_packus_epi16(avg, avg));
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn h2v2_downsample_avx2(input0: *const u8, input1: *const u8, output: *mut u8, output_cols: usize) -> usize {
+    let bias = _mm256_set1_epi32(1 | (2 << 16));
+    let mask = _mm256_set1_epi16(0xFF);
+    let chunks = output_cols / 16;
+    for col in (0..chunks * 16).step_by(16) {
+        let p0 = _mm256_loadu_si256(input0.add(col * 2).cast());
+        let p1 = _mm256_loadu_si256(input1.add(col * 2).cast());
+        let sums0 = _mm256_add_epi16(_mm256_and_si256(p0, mask), _mm256_srli_epi16(p0, 8));
+        let sums1 = _mm256_add_epi16(_mm256_and_si256(p1, mask), _mm256_srli_epi16(p1, 8));
+        let avg = _mm256_srli_epi16(_mm256_add_epi16(_mm256_add_epi16(sums0, sums1), bias), 2);
+        store_packed_avx2(output.add(col), avg);
+    }
+    chunks * 16
+}
+
+/// Transposes 8×8 16-bit elements
+unsafe fn transpose(r: [__m128i; 8]) -> [__m128i; 8] {
+    let a = [
+        _mm_unpacklo_epi16(r[0], r[1]),
+        _mm_unpackhi_epi16(r[0], r[1]),
+        _mm_unpacklo_epi16(r[2], r[3]),
+        _mm_unpackhi_epi16(r[2], r[3]),
+        _mm_unpacklo_epi16(r[4], r[5]),
+        _mm_unpackhi_epi16(r[4], r[5]),
+        _mm_unpacklo_epi16(r[6], r[7]),
+        _mm_unpackhi_epi16(r[6], r[7]),
+    ];
+    let b = [
+        _mm_unpacklo_epi32(a[0], a[2]),
+        _mm_unpackhi_epi32(a[0], a[2]),
+        _mm_unpacklo_epi32(a[1], a[3]),
+        _mm_unpackhi_epi32(a[1], a[3]),
+        _mm_unpacklo_epi32(a[4], a[6]),
+        _mm_unpackhi_epi32(a[4], a[6]),
+        _mm_unpacklo_epi32(a[5], a[7]),
+        _mm_unpackhi_epi32(a[5], a[7]),
+    ];
+    [
+        _mm_unpacklo_epi64(b[0], b[4]),
+        _mm_unpackhi_epi64(b[0], b[4]),
+        _mm_unpacklo_epi64(b[1], b[5]),
+        _mm_unpackhi_epi64(b[1], b[5]),
+        _mm_unpacklo_epi64(b[2], b[6]),
+        _mm_unpackhi_epi64(b[2], b[6]),
+        _mm_unpacklo_epi64(b[3], b[7]),
+        _mm_unpackhi_epi64(b[3], b[7]),
+    ]
+}
+
+unsafe fn load_block(data: *const DCTELEM) -> [__m128i; 8] {
+    [0, 1, 2, 3, 4, 5, 6, 7].map(|row| _mm_loadu_si128(data.add(row * DCTSIZE).cast()))
+}
+
+unsafe fn store_block(data: *mut DCTELEM, rows: [__m128i; 8]) {
+    for (row, v) in rows.into_iter().enumerate() {
+        _mm_storeu_si128(data.add(row * DCTSIZE).cast(), v);
+    }
+}
+
+/// Both passes work on vectors of the same coefficient of 8 rows (or columns), so the block is transposed around pass 1
+pub(crate) unsafe fn fdct_islow(data: *mut DCTELEM) {
+    if avx2() {
+        fdct_islow_avx2(data);
+        return;
+    }
+    let columns = islow_pass_sse2(transpose(load_block(data)), true);
+    store_block(data, islow_pass_sse2(transpose(columns), false));
+}
+
+unsafe fn islow_pass_sse2(d: [__m128i; 8], pass1: bool) -> [__m128i; 8] {
+    // sign-extension of 16-bit lanes to 32 bits
+    let lo = islow_pass_half_sse2(d.map(|v| _mm_srai_epi32(_mm_unpacklo_epi16(v, v), 16)), pass1);
+    let hi = islow_pass_half_sse2(d.map(|v| _mm_srai_epi32(_mm_unpackhi_epi16(v, v), 16)), pass1);
+    [0, 1, 2, 3, 4, 5, 6, 7].map(|i| _mm_packs_epi32(lo[i], hi[i]))
+}
+
+/// One pass of `jpeg_fdct_islow` on 32-bit lanes
+unsafe fn islow_pass_half_sse2(d: [__m128i; 8], pass1: bool) -> [__m128i; 8] {
+    // SSE2 has no 32-bit `mullo`, so even and odd lanes are multiplied separately
+    let mul = |v: __m128i, c: i32| {
+        let c = _mm_set1_epi32(c);
+        let even = _mm_mul_epu32(v, c);
+        let odd = _mm_mul_epu32(_mm_srli_si128(v, 4), c);
+        _mm_unpacklo_epi32(_mm_shuffle_epi32(even, 0b1000), _mm_shuffle_epi32(odd, 0b1000))
+    };
+    let descale = |v: __m128i, n: u32| _mm_sra_epi32(_mm_add_epi32(v, _mm_set1_epi32(1 << (n - 1))), _mm_cvtsi32_si128(n as i32));
+    let bits = if pass1 { CONST_BITS - PASS1_BITS } else { CONST_BITS + PASS1_BITS };
+    let (add, sub) = (|a, b| _mm_add_epi32(a, b), |a, b| _mm_sub_epi32(a, b));
+
+    let tmp0 = add(d[0], d[7]);
+    let tmp7 = sub(d[0], d[7]);
+    let tmp1 = add(d[1], d[6]);
+    let tmp6 = sub(d[1], d[6]);
+    let tmp2 = add(d[2], d[5]);
+    let tmp5 = sub(d[2], d[5]);
+    let tmp3 = add(d[3], d[4]);
+    let tmp4 = sub(d[3], d[4]);
+
+    let tmp10 = add(tmp0, tmp3);
+    let tmp13 = sub(tmp0, tmp3);
+    let tmp11 = add(tmp1, tmp2);
+    let tmp12 = sub(tmp1, tmp2);
+
+    let mut out = [_mm_setzero_si128(); 8];
+    if pass1 {
+        out[0] = _mm_slli_epi32(add(tmp10, tmp11), PASS1_BITS as i32);
+        out[4] = _mm_slli_epi32(sub(tmp10, tmp11), PASS1_BITS as i32);
+    } else {
+        out[0] = descale(add(tmp10, tmp11), PASS1_BITS);
+        out[4] = descale(sub(tmp10, tmp11), PASS1_BITS);
+    }
+
+    let z1 = mul(add(tmp12, tmp13), FIX_0_541196100);
+    out[2] = descale(add(z1, mul(tmp13, FIX_0_765366865)), bits);
+    out[6] = descale(add(z1, mul(tmp12, -FIX_1_847759065)), bits);
+
+    let z1 = add(tmp4, tmp7);
+    let z2 = add(tmp5, tmp6);
+    let z3 = add(tmp4, tmp6);
+    let z4 = add(tmp5, tmp7);
+    let z5 = mul(add(z3, z4), FIX_1_175875602);
+
+    let tmp4 = mul(tmp4, FIX_0_298631336);
+    let tmp5 = mul(tmp5, FIX_2_053119869);
+    let tmp6 = mul(tmp6, FIX_3_072711026);
+    let tmp7 = mul(tmp7, FIX_1_501321110);
+    let z1 = mul(z1, -FIX_0_899976223);
+    let z2 = mul(z2, -FIX_2_562915447);
+    let z3 = add(mul(z3, -FIX_1_961570560), z5);
+    let z4 = add(mul(z4, -FIX_0_390180644), z5);
+
+    out[7] = descale(add(add(tmp4, z1), z3), bits);
+    out[5] = descale(add(add(tmp5, z2), z4), bits);
+    out[3] = descale(add(add(tmp6, z2), z3), bits);
+    out[1] = descale(add(add(tmp7, z1), z4), bits);
+    out
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn fdct_islow_avx2(data: *mut DCTELEM) {
+    let columns = islow_pass_avx2(transpose(load_block(data)), true);
+    store_block(data, islow_pass_avx2(transpose(columns), false));
+}
+
+/// One pass of `jpeg_fdct_islow`, with all 8 rows (or columns) in 32-bit lanes of one vector
+#[target_feature(enable = "avx2")]
+unsafe fn islow_pass_avx2(d: [__m128i; 8], pass1: bool) -> [__m128i; 8] {
+    let d = d.map(|v| _mm256_cvtepi16_epi32(v));
+    let mul = |v: __m256i, c: i32| _mm256_mullo_epi32(v, _mm256_set1_epi32(c));
+    let descale = |v: __m256i, n: u32| _mm256_sra_epi32(_mm256_add_epi32(v, _mm256_set1_epi32(1 << (n - 1))), _mm_cvtsi32_si128(n as i32));
+    let bits = if pass1 { CONST_BITS - PASS1_BITS } else { CONST_BITS + PASS1_BITS };
+    let (add, sub) = (|a, b| _mm256_add_epi32(a, b), |a, b| _mm256_sub_epi32(a, b));
+
+    let tmp0 = add(d[0], d[7]);
+    let tmp7 = sub(d[0], d[7]);
+    let tmp1 = add(d[1], d[6]);
+    let tmp6 = sub(d[1], d[6]);
+    let tmp2 = add(d[2], d[5]);
+    let tmp5 = sub(d[2], d[5]);
+    let tmp3 = add(d[3], d[4]);
+    let tmp4 = sub(d[3], d[4]);
+
+    let tmp10 = add(tmp0, tmp3);
+    let tmp13 = sub(tmp0, tmp3);
+    let tmp11 = add(tmp1, tmp2);
+    let tmp12 = sub(tmp1, tmp2);
+
+    let mut out = [_mm256_setzero_si256(); 8];
+    if pass1 {
+        out[0] = _mm256_slli_epi32(add(tmp10, tmp11), PASS1_BITS as i32);
+        out[4] = _mm256_slli_epi32(sub(tmp10, tmp11), PASS1_BITS as i32);
+    } else {
+        out[0] = descale(add(tmp10, tmp11), PASS1_BITS);
+        out[4] = descale(sub(tmp10, tmp11), PASS1_BITS);
+    }
+
+    let z1 = mul(add(tmp12, tmp13), FIX_0_541196100);
+    out[2] = descale(add(z1, mul(tmp13, FIX_0_765366865)), bits);
+    out[6] = descale(add(z1, mul(tmp12, -FIX_1_847759065)), bits);
+
+    let z1 = add(tmp4, tmp7);
+    let z2 = add(tmp5, tmp6);
+    let z3 = add(tmp4, tmp6);
+    let z4 = add(tmp5, tmp7);
+    let z5 = mul(add(z3, z4), FIX_1_175875602);
+
+    let tmp4 = mul(tmp4, FIX_0_298631336);
+    let tmp5 = mul(tmp5, FIX_2_053119869);
+    let tmp6 = mul(tmp6, FIX_3_072711026);
+    let tmp7 = mul(tmp7, FIX_1_501321110);
+    let z1 = mul(z1, -FIX_0_899976223);
+    let z2 = mul(z2, -FIX_2_562915447);
+    let z3 = add(mul(z3, -FIX_1_961570560), z5);
+    let z4 = add(mul(z4, -FIX_0_390180644), z5);
+
+    out[7] = descale(add(add(tmp4, z1), z3), bits);
+    out[5] = descale(add(add(tmp5, z2), z4), bits);
+    out[3] = descale(add(add(tmp6, z2), z3), bits);
+    out[1] = descale(add(add(tmp7, z1), z4), bits);
+    out.map(|v| _mm_packs_epi32(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1)))
+}
+
+/// There's no AVX2 version, like in libjpeg-turbo
+pub(crate) unsafe fn fdct_ifast(data: *mut DCTELEM) {
+    let columns = ifast_pass(transpose(load_block(data)));
+    store_block(data, ifast_pass(transpose(columns)));
+}
+
+/// One pass of `jpeg_fdct_ifast`, which is the same for rows and columns
+unsafe fn ifast_pass(d: [__m128i; 8]) -> [__m128i; 8] {
+    // `MULTIPLY` truncates, because `USE_ACCURATE_ROUNDING` isn't defined.
+    // Bits 8..24 of the 32-bit product are the high byte of the low half and the low byte of the high half.
+    let mul = |v: __m128i, c: i32| {
+        let c = _mm_set1_epi16(c as i16);
+        _mm_or_si128(_mm_srli_epi16(_mm_mullo_epi16(v, c), 8), _mm_slli_epi16(_mm_mulhi_epi16(v, c), 8))
+    };
+    let (add, sub) = (|a, b| _mm_add_epi16(a, b), |a, b| _mm_sub_epi16(a, b));
+
+    let tmp0 = add(d[0], d[7]);
+    let tmp7 = sub(d[0], d[7]);
+    let tmp1 = add(d[1], d[6]);
+    let tmp6 = sub(d[1], d[6]);
+    let tmp2 = add(d[2], d[5]);
+    let tmp5 = sub(d[2], d[5]);
+    let tmp3 = add(d[3], d[4]);
+    let tmp4 = sub(d[3], d[4]);
+
+    let tmp10 = add(tmp0, tmp3);
+    let tmp13 = sub(tmp0, tmp3);
+    let tmp11 = add(tmp1, tmp2);
+    let tmp12 = sub(tmp1, tmp2);
+
+    let mut out = [_mm_setzero_si128(); 8];
+    out[0] = add(tmp10, tmp11);
+    out[4] = sub(tmp10, tmp11);
+
+    let z1 = mul(add(tmp12, tmp13), FIX_0_707106781);
+    out[2] = add(tmp13, z1);
+    out[6] = sub(tmp13, z1);
+
+    let tmp10 = add(tmp4, tmp5);
+    let tmp11 = add(tmp5, tmp6);
+    let tmp12 = add(tmp6, tmp7);
+
+    let z5 = mul(sub(tmp10, tmp12), FIX_0_382683433);
+    let z2 = add(mul(tmp10, FIX_0_541196100_FAST), z5);
+    let z4 = add(mul(tmp12, FIX_1_306562965), z5);
+    let z3 = mul(tmp11, FIX_0_707106781);
+
+    let z11 = add(tmp7, z3);
+    let z13 = sub(tmp7, z3);
+
+    out[5] = add(z13, z2);
+    out[3] = sub(z13, z2);
+    out[1] = add(z11, z4);
+    out[7] = sub(z11, z4);
+    out
+}
+
+/// Like the nasm version, this uses the `scale` row of `divisors` instead of a per-coefficient shift.
+/// `jcdctmgr.c` only uses it when `shift` is positive for all coefficients.
+pub(crate) unsafe fn quantize(coef_block: *mut JCOEF, divisors: *const DCTELEM, workspace: *const DCTELEM) {
+    if avx2() {
+        quantize_avx2(coef_block, divisors, workspace);
+        return;
+    }
+    for i in (0..DCTSIZE2).step_by(8) {
+        let coefs = _mm_loadu_si128(workspace.add(i).cast());
+        let recip = _mm_loadu_si128(divisors.add(i).cast());
+        let corr = _mm_loadu_si128(divisors.add(i + DCTSIZE2).cast());
+        let scale = _mm_loadu_si128(divisors.add(i + DCTSIZE2 * 2).cast());
+
+        let sign = _mm_srai_epi16(coefs, 15);
+        let abs = _mm_sub_epi16(_mm_xor_si128(coefs, sign), sign);
+        let product = _mm_mulhi_epu16(_mm_add_epi16(abs, corr), recip);
+        let product = _mm_mulhi_epu16(product, scale);
+        _mm_storeu_si128(coef_block.add(i).cast(), _mm_sub_epi16(_mm_xor_si128(product, sign), sign));
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn quantize_avx2(coef_block: *mut JCOEF, divisors: *const DCTELEM, workspace: *const DCTELEM) {
+    for i in (0..DCTSIZE2).step_by(16) {
+        let coefs = _mm256_loadu_si256(workspace.add(i).cast());
+        let recip = _mm256_loadu_si256(divisors.add(i).cast());
+        let corr = _mm256_loadu_si256(divisors.add(i + DCTSIZE2).cast());
+        let scale = _mm256_loadu_si256(divisors.add(i + DCTSIZE2 * 2).cast());
+
+        let sign = _mm256_srai_epi16(coefs, 15);
+        let abs = _mm256_add_epi16(_mm256_abs_epi16(coefs), corr);
+        let product = _mm256_mulhi_epu16(_mm256_mulhi_epu16(abs, recip), scale);
+        _mm256_storeu_si256(coef_block.add(i).cast(), _mm256_sub_epi16(_mm256_xor_si256(product, sign), sign));
+    }
+}
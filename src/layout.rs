@@ -0,0 +1,48 @@
+//! Compares the Rust structs with their layout in C, as seen by the C compiler for the target.
+//! The C side and the list of fields are generated by `layout_probe()` in `build.rs`.
+//! If the probe can't be generated, the build prints a warning and this test is left out.
+use crate::*;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+extern "C" {
+    fn mozjpeg_layout_probe(len: &mut usize) -> *const usize;
+}
+
+/// Sizes, alignments and field offsets in the same order as the C table
+macro_rules! layout_probe {
+    ($($name:ident: $($($field:ident).+)*;)*) => {
+        fn rust_layout() -> Vec<(&'static str, usize)> {
+            let mut layout = Vec::new();
+            $(
+                layout.push((concat!("size of ", stringify!($name)), mem::size_of::<$name>()));
+                layout.push((concat!("alignment of ", stringify!($name)), mem::align_of::<$name>()));
+                let s = MaybeUninit::<$name>::uninit();
+                let base = s.as_ptr();
+                $(
+                    let field = unsafe { ptr::addr_of!((*base).$($field).+) };
+                    layout.push((concat!("offset of ", stringify!($name), "::", stringify!($($field).+)), field as usize - base as usize));
+                )*
+            )*
+            layout
+        }
+    };
+}
+
+include!(concat!(env!("OUT_DIR"), "/layout_probe.rs"));
+
+#[test]
+fn layout_matches_c() {
+    let rust = rust_layout();
+    let c = unsafe {
+        let mut len = 0;
+        let table = mozjpeg_layout_probe(&mut len);
+        std::slice::from_raw_parts(table, len)
+    };
+    assert_eq!(rust.len(), c.len());
+    let mismatched: Vec<_> = rust.iter().zip(c)
+        .filter(|&(&(_, rust), &c)| rust != c)
+        .map(|(&(what, rust), c)| format!("{what} is {rust} in Rust, but {c} in C"))
+        .collect();
+    assert!(mismatched.is_empty(), "JPEG_LIB_VERSION {JPEG_LIB_VERSION}: {mismatched:#?}");
+}
@@ -12,8 +12,67 @@ pub use libc::FILE;
 #[cfg(target_arch = "wasm32")]
 pub use std::os::raw::c_void as FILE;
 
+/// Declares functions from the C library, which are prefixed with `mozjpeg_` when the `prefix_symbols` feature is enabled
+macro_rules! extern_c {
+    (extern $abi:literal {
+        $($(#[$attr:meta])* $vis:vis fn $name:ident $(<$($lt:lifetime),*>)? ($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*
+    }) => {
+        extern $abi {
+            $(
+                $(#[$attr])*
+                #[cfg_attr(feature = "prefix_symbols", link_name = concat!("mozjpeg_", stringify!($name)))]
+                $vis fn $name $(<$($lt),*>)? ($($arg: $ty),*) $(-> $ret)?;
+            )*
+        }
+    };
+}
+
 mod jerror;
 pub use jerror::*;
+mod message;
+#[cfg(any(feature = "rust_alloc", all(target_arch = "wasm32", target_os = "unknown")))]
+mod jmemsys;
+#[cfg(mozjpeg_rust_simd)]
+mod jsimd_rust;
+#[cfg(all(feature = "wasm_simd", target_arch = "wasm32"))]
+mod jsimd_wasm;
+#[cfg(mozjpeg_portable_simd)]
+mod jsimd_portable;
+#[cfg(mozjpeg_x86_intrinsics_simd)]
+mod jsimd_x86;
+mod simd;
+pub use simd::*;
+#[cfg(feature = "precision12")]
+pub mod precision12;
+#[cfg(all(test, mozjpeg_layout_probe))]
+mod layout;
+
+#[cfg(feature = "safe_api")]
+mod error;
+#[cfg(feature = "safe_api")]
+pub use error::{catch, Error, ErrorMgr, Warning};
+#[cfg(feature = "safe_api")]
+mod limits;
+#[cfg(feature = "safe_api")]
+pub use limits::*;
+#[cfg(feature = "safe_api")]
+mod memory;
+#[cfg(feature = "safe_api")]
+pub use memory::*;
+#[cfg(feature = "safe_api")]
+mod progress;
+#[cfg(feature = "safe_api")]
+pub use progress::*;
+#[cfg(any(feature = "validate", feature = "lossless"))]
+mod session;
+#[cfg(feature = "validate")]
+mod validate;
+#[cfg(feature = "validate")]
+pub use validate::*;
+#[cfg(feature = "lossless")]
+mod lossless;
+#[cfg(feature = "lossless")]
+pub use lossless::*;
 
 pub use JINT_COMPRESS_PROFILE_VALUE::*;
 pub use J_BOOLEAN_PARAM::*;
@@ -63,9 +122,9 @@ pub type JBLOCKARRAY = *mut JBLOCKROW;
 
 // must match dct.h; assumes bits in sample == 8
 /// type for individual integer DCT coefficient
-#[cfg(feature = "nasm_simd")]
+#[cfg(mozjpeg_simd)]
 pub type DCTELEM = i16;
-#[cfg(not(feature = "nasm_simd"))]
+#[cfg(not(mozjpeg_simd))]
 pub type DCTELEM = c_int;
 
 #[cfg(feature = "jpegtran")]
@@ -374,7 +433,7 @@ pub struct jpeg_compress_struct {
     /// TRUE=optimize entropy encoding parms
     pub optimize_coding: boolean,
     pub CCIR601_sampling: boolean,
-    /// TRUE=apply fancy downsampling
+    /// TRUE=apply fancy downsampling (ignored by MozJPEG)
     #[cfg(feature = "jpeg70_abi")]
     pub do_fancy_downsampling: boolean,
     pub smoothing_factor: c_int,
@@ -607,8 +666,8 @@ pub struct jpeg_error_mgr {
     pub error_exit: Option<unsafe extern "C-unwind" fn(cinfo: &mut jpeg_common_struct)>,
     pub emit_message: Option<unsafe extern "C-unwind" fn(cinfo: &mut jpeg_common_struct, msg_level: c_int)>,
     pub output_message: Option<unsafe extern "C-unwind" fn(cinfo: &mut jpeg_common_struct)>,
-    /// WARNING: incorrect definition. It should have been `buffer: &mut [u8; 80usize]`
-    pub format_message: Option<unsafe extern "C-unwind" fn(cinfo: &mut jpeg_common_struct, buffer: &[u8; 80usize])>,
+    /// Formats the message into the buffer (`JMSG_LENGTH_MAX`). See also `jpeg_error_mgr::message()`.
+    pub format_message: Option<unsafe extern "C-unwind" fn(cinfo: &mut jpeg_common_struct, buffer: &mut [u8; 80usize])>,
     pub reset_error_mgr: Option<unsafe extern "C-unwind" fn(cinfo: &mut jpeg_common_struct)>,
     pub msg_code: c_int,
     pub msg_parm: msg_parm_union,
@@ -627,10 +686,10 @@ pub struct msg_parm_union {
 }
 impl msg_parm_union {
     pub unsafe fn i(&mut self) -> *mut [c_int; 8usize] {
-        ::std::mem::transmute(&self._bindgen_data_)
+        ::std::ptr::addr_of_mut!(self._bindgen_data_).cast()
     }
     pub unsafe fn s(&mut self) -> *mut [i8; 80usize] {
-        ::std::mem::transmute(&self._bindgen_data_)
+        ::std::ptr::addr_of_mut!(self._bindgen_data_).cast()
     }
 }
 impl Default for msg_parm_union {
@@ -740,137 +799,137 @@ pub unsafe fn jpeg_create_compress(cinfo: *mut jpeg_compress_struct) {
     jpeg_CreateCompress(cinfo, JPEG_LIB_VERSION, mem::size_of::<jpeg_compress_struct>());
 }
 
-extern "C-unwind" {
-    pub fn jpeg_std_error<'a>(err: &'a mut jpeg_error_mgr) -> &'a mut jpeg_error_mgr;
-    pub fn jpeg_CreateCompress(cinfo: *mut jpeg_compress_struct, version: c_int, structsize: usize);
-    pub fn jpeg_CreateDecompress(cinfo: *mut jpeg_decompress_struct, version: c_int, structsize: usize);
-    pub fn jpeg_destroy_compress(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_destroy_decompress(cinfo: &mut jpeg_decompress_struct);
-    #[cfg(not(target_arch = "wasm32"))]
-    pub fn jpeg_stdio_dest(cinfo: &mut jpeg_compress_struct, outfile: *mut FILE);
-    #[cfg(not(target_arch = "wasm32"))]
-    pub fn jpeg_stdio_src(cinfo: &mut jpeg_decompress_struct, infile: *mut FILE);
-    pub fn jpeg_mem_dest(cinfo: &mut jpeg_compress_struct,
-                     outbuffer: *mut *mut u8,
-                     outsize: *mut c_ulong);
-    pub fn jpeg_mem_src(cinfo: &mut jpeg_decompress_struct,
-                    inbuffer: *const u8,
-                    insize: c_ulong);
-    pub fn jpeg_set_defaults(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_set_colorspace(cinfo: &mut jpeg_compress_struct, colorspace: J_COLOR_SPACE);
-    pub fn jpeg_default_colorspace(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_set_quality(cinfo: &mut jpeg_compress_struct, quality: c_int, force_baseline: boolean);
-    pub fn jpeg_set_linear_quality(cinfo: &mut jpeg_compress_struct,
-                               scale_factor: c_int,
-                               force_baseline: boolean);
-    pub fn jpeg_add_quant_table(cinfo: &mut jpeg_compress_struct,
-                            which_tbl: c_int,
-                            basic_table: *const c_uint,
-                            scale_factor: c_int,
-                            force_baseline: boolean);
-    pub fn jpeg_quality_scaling(quality: c_int) -> c_int;
-    pub fn jpeg_float_quality_scaling(quality: f32) -> f32;
-    pub fn jpeg_simple_progression(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_suppress_tables(cinfo: &mut jpeg_compress_struct, suppress: boolean);
-    pub fn jpeg_alloc_quant_table(cinfo: &mut jpeg_common_struct) -> *mut JQUANT_TBL;
-    pub fn jpeg_alloc_huff_table(cinfo: &mut jpeg_common_struct) -> *mut JHUFF_TBL;
-    pub fn jpeg_start_compress(cinfo: &mut jpeg_compress_struct, write_all_tables: boolean);
-    pub fn jpeg_write_scanlines(cinfo: &mut jpeg_compress_struct, scanlines: JSAMPARRAY,
-                            num_lines: JDIMENSION) -> JDIMENSION;
-    pub fn jpeg_finish_compress(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_write_raw_data(cinfo: &mut jpeg_compress_struct, data: JSAMPIMAGE,
-                           num_lines: JDIMENSION) -> JDIMENSION;
-    pub fn jpeg_write_marker(cinfo: &mut jpeg_compress_struct, marker: c_int,
-                         dataptr: *const u8, datalen: c_uint);
-    pub fn jpeg_write_m_header(cinfo: &mut jpeg_compress_struct, marker: c_int, datalen: c_uint);
-    pub fn jpeg_write_m_byte(cinfo: &mut jpeg_compress_struct, val: c_int);
-    pub fn jpeg_write_tables(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_read_header(cinfo: &mut jpeg_decompress_struct, require_image: boolean) -> c_int;
-    pub fn jpeg_start_decompress(cinfo: &mut jpeg_decompress_struct) -> boolean;
-    pub fn jpeg_read_scanlines(cinfo: &mut jpeg_decompress_struct, scanlines: JSAMPARRAY_MUT,
-                           max_lines: JDIMENSION) -> JDIMENSION;
-    pub fn jpeg_finish_decompress(cinfo: &mut jpeg_decompress_struct) -> boolean;
-    pub fn jpeg_read_raw_data(cinfo: &mut jpeg_decompress_struct, data: JSAMPIMAGE_MUT,
-                          max_lines: JDIMENSION) -> JDIMENSION;
-    /// The ICC has defined a standard for including such data in JPEG "APP2" markers.
-    /// The aforementioned functions do not know anything about the internal structure
-    /// of the ICC profile data; they just know how to embed the profile data into a
-    /// JPEG file while writing it, or to extract the profile data from a JPEG file
-    /// while reading it.
-    /// This memory region is allocated by the library using malloc() and must be freed
-    /// by the caller using free() when the memory region is no longer needed.
-    /// Callers wishing to use jpeg_read_icc_profile() must call jpeg_save_markers(cinfo, JPEG_APP0 + 2, 0xFFFF);
-    /// prior to calling jpeg_read_header(). jpeg_read_icc_profile() can be called at
-    /// any point between jpeg_read_header() and jpeg_finish_decompress().
-    #[cfg(feature = "icc_io")]
-    pub fn jpeg_read_icc_profile(cinfo: &mut jpeg_decompress_struct, icc_data_ptr: *mut *mut u8, icc_data_len: *mut c_uint) -> boolean;
-    /// jpeg_write_icc_profile() must be called after calling jpeg_start_compress() and
-    /// before the first call to jpeg_write_scanlines() or jpeg_write_raw_data().  This
-    /// ordering ensures that the APP2 marker(s) will appear after the SOI and JFIF or
-    /// Adobe markers, but before all other data.
-    #[cfg(feature = "icc_io")]
-    pub fn jpeg_write_icc_profile(cinfo: &mut jpeg_compress_struct, icc_data_ptr: *const u8, icc_data_len: c_uint);
-    /// This function provides application programmers with the ability to skip over
-    /// multiple rows in the JPEG image.
-    ///
-    /// Suspending data sources are not supported by this function.  Calling
-    /// jpeg_skip_scanlines() with a suspending data source will result in undefined
-    /// behavior.
-    pub fn jpeg_skip_scanlines(cinfo: &mut jpeg_decompress_struct, num_lines: JDIMENSION) -> JDIMENSION;
-    /// This function provides application programmers with the ability to decompress
-    /// only a portion of each row in the JPEG image.  It must be called after
-    /// jpeg_start_decompress() and before any calls to jpeg_read_scanlines() or
-    /// jpeg_skip_scanlines().
-    /// If the output image is scaled, then xoffset and width are relative to the scaled image dimensions.
-    /// xoffset and width are passed by reference because xoffset must fall on an iMCU
-    /// boundary.  If it doesn't, then it will be moved left to the nearest iMCU
-    /// boundary, and width will be increased accordingly.
-    pub fn jpeg_crop_scanline(cinfo: &mut jpeg_decompress_struct, xoffset: &mut JDIMENSION, width: &mut JDIMENSION);
-    pub fn jpeg_has_multiple_scans(cinfo: &jpeg_decompress_struct) -> boolean;
-    pub fn jpeg_start_output(cinfo: &mut jpeg_decompress_struct, scan_number: c_int) -> boolean;
-    pub fn jpeg_finish_output(cinfo: &mut jpeg_decompress_struct) -> boolean;
-    pub fn jpeg_input_complete(cinfo: &jpeg_decompress_struct) -> boolean;
-    #[deprecated]
-    pub fn jpeg_new_colormap(cinfo: &mut jpeg_decompress_struct);
-    pub fn jpeg_consume_input(cinfo: &mut jpeg_decompress_struct) -> c_int;
-    /// Precalculate JPEG dimensions for current compression parameters
-    #[cfg(feature = "jpeg70_abi")]
-    pub fn jpeg_calc_jpeg_dimensions(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_calc_output_dimensions(cinfo: &mut jpeg_decompress_struct);
-    pub fn jpeg_save_markers(cinfo: &mut jpeg_decompress_struct,
-                         marker_code: c_int,
-                         length_limit: c_uint);
-    pub fn jpeg_set_marker_processor(cinfo: &mut jpeg_decompress_struct,
-                                 marker_code: c_int,
-                                 routine: jpeg_marker_parser_method);
-    pub fn jpeg_read_coefficients(cinfo: &mut jpeg_decompress_struct) -> *mut *mut jvirt_barray_control;
-    pub fn jpeg_write_coefficients(cinfo: &mut jpeg_compress_struct,
-                               coef_arrays: *mut *mut jvirt_barray_control);
-    pub fn jpeg_copy_critical_parameters(srcinfo: &jpeg_decompress_struct,
-                                     dstinfo: &mut jpeg_compress_struct);
-    #[cfg(feature = "jpeg80_abi")]
-    pub fn jpeg_core_output_dimensions(cinfo: &mut jpeg_decompress_struct);
-    pub fn jpeg_abort_compress(cinfo: &mut jpeg_compress_struct);
-    pub fn jpeg_abort_decompress(cinfo: &mut jpeg_decompress_struct);
-    pub fn jpeg_resync_to_restart(cinfo: &mut jpeg_decompress_struct, desired: c_int) -> boolean;
-    pub fn jpeg_c_bool_param_supported(cinfo: &jpeg_compress_struct,
-                                   param: J_BOOLEAN_PARAM) -> boolean;
-    pub fn jpeg_c_set_bool_param(cinfo: &mut jpeg_compress_struct,
-                             param: J_BOOLEAN_PARAM, value: boolean);
-    pub fn jpeg_c_get_bool_param(cinfo: &jpeg_compress_struct,
-                             param: J_BOOLEAN_PARAM) -> boolean;
-    pub fn jpeg_c_float_param_supported(cinfo: &jpeg_compress_struct, param: J_FLOAT_PARAM) -> boolean;
-    pub fn jpeg_abort(cinfo: &mut jpeg_common_struct);
-    pub fn jpeg_destroy(cinfo: &mut jpeg_common_struct);
-    pub fn jpeg_c_set_float_param(cinfo: &mut jpeg_compress_struct, param: J_FLOAT_PARAM, value: f32);
-    pub fn jpeg_c_get_float_param(cinfo: &jpeg_compress_struct, param: J_FLOAT_PARAM) -> f32;
-    pub fn jpeg_c_int_param_supported(cinfo: &jpeg_compress_struct, param: J_INT_PARAM) -> boolean;
-    pub fn jpeg_c_set_int_param(cinfo: &mut jpeg_compress_struct, param: J_INT_PARAM, value: c_int);
-    pub fn jpeg_c_get_int_param(cinfo: &jpeg_compress_struct, param: J_INT_PARAM) -> c_int;
-    pub fn jpeg_set_idct_method_selector(cinfo: &jpeg_compress_struct, param: *const c_void);
-    #[cfg(test)] #[allow(dead_code)] fn jsimd_can_rgb_ycc() -> c_int;
-    #[cfg(test)] #[allow(dead_code)] fn jsimd_can_fdct_ifast() -> c_int;
-    #[cfg(test)] #[allow(dead_code)] fn jsimd_fdct_ifast(block: *mut DCTELEM);
+extern_c! {
+    extern "C-unwind" {
+        pub fn jpeg_std_error<'a>(err: &'a mut jpeg_error_mgr) -> &'a mut jpeg_error_mgr;
+        pub fn jpeg_CreateCompress(cinfo: *mut jpeg_compress_struct, version: c_int, structsize: usize);
+        pub fn jpeg_CreateDecompress(cinfo: *mut jpeg_decompress_struct, version: c_int, structsize: usize);
+        pub fn jpeg_destroy_compress(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_destroy_decompress(cinfo: &mut jpeg_decompress_struct);
+        #[cfg(not(target_arch = "wasm32"))]
+        pub fn jpeg_stdio_dest(cinfo: &mut jpeg_compress_struct, outfile: *mut FILE);
+        #[cfg(not(target_arch = "wasm32"))]
+        pub fn jpeg_stdio_src(cinfo: &mut jpeg_decompress_struct, infile: *mut FILE);
+        pub fn jpeg_mem_dest(cinfo: &mut jpeg_compress_struct,
+                         outbuffer: *mut *mut u8,
+                         outsize: *mut c_ulong);
+        pub fn jpeg_mem_src(cinfo: &mut jpeg_decompress_struct,
+                        inbuffer: *const u8,
+                        insize: c_ulong);
+        pub fn jpeg_set_defaults(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_set_colorspace(cinfo: &mut jpeg_compress_struct, colorspace: J_COLOR_SPACE);
+        pub fn jpeg_default_colorspace(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_set_quality(cinfo: &mut jpeg_compress_struct, quality: c_int, force_baseline: boolean);
+        pub fn jpeg_set_linear_quality(cinfo: &mut jpeg_compress_struct,
+                                   scale_factor: c_int,
+                                   force_baseline: boolean);
+        pub fn jpeg_add_quant_table(cinfo: &mut jpeg_compress_struct,
+                                which_tbl: c_int,
+                                basic_table: *const c_uint,
+                                scale_factor: c_int,
+                                force_baseline: boolean);
+        pub fn jpeg_quality_scaling(quality: c_int) -> c_int;
+        pub fn jpeg_float_quality_scaling(quality: f32) -> f32;
+        pub fn jpeg_simple_progression(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_suppress_tables(cinfo: &mut jpeg_compress_struct, suppress: boolean);
+        pub fn jpeg_alloc_quant_table(cinfo: &mut jpeg_common_struct) -> *mut JQUANT_TBL;
+        pub fn jpeg_alloc_huff_table(cinfo: &mut jpeg_common_struct) -> *mut JHUFF_TBL;
+        pub fn jpeg_start_compress(cinfo: &mut jpeg_compress_struct, write_all_tables: boolean);
+        pub fn jpeg_write_scanlines(cinfo: &mut jpeg_compress_struct, scanlines: JSAMPARRAY,
+                                num_lines: JDIMENSION) -> JDIMENSION;
+        pub fn jpeg_finish_compress(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_write_raw_data(cinfo: &mut jpeg_compress_struct, data: JSAMPIMAGE,
+                               num_lines: JDIMENSION) -> JDIMENSION;
+        pub fn jpeg_write_marker(cinfo: &mut jpeg_compress_struct, marker: c_int,
+                             dataptr: *const u8, datalen: c_uint);
+        pub fn jpeg_write_m_header(cinfo: &mut jpeg_compress_struct, marker: c_int, datalen: c_uint);
+        pub fn jpeg_write_m_byte(cinfo: &mut jpeg_compress_struct, val: c_int);
+        pub fn jpeg_write_tables(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_read_header(cinfo: &mut jpeg_decompress_struct, require_image: boolean) -> c_int;
+        pub fn jpeg_start_decompress(cinfo: &mut jpeg_decompress_struct) -> boolean;
+        pub fn jpeg_read_scanlines(cinfo: &mut jpeg_decompress_struct, scanlines: JSAMPARRAY_MUT,
+                               max_lines: JDIMENSION) -> JDIMENSION;
+        pub fn jpeg_finish_decompress(cinfo: &mut jpeg_decompress_struct) -> boolean;
+        pub fn jpeg_read_raw_data(cinfo: &mut jpeg_decompress_struct, data: JSAMPIMAGE_MUT,
+                              max_lines: JDIMENSION) -> JDIMENSION;
+        /// The ICC has defined a standard for including such data in JPEG "APP2" markers.
+        /// The aforementioned functions do not know anything about the internal structure
+        /// of the ICC profile data; they just know how to embed the profile data into a
+        /// JPEG file while writing it, or to extract the profile data from a JPEG file
+        /// while reading it.
+        /// This memory region is allocated by the library using malloc() and must be freed
+        /// by the caller using free() when the memory region is no longer needed.
+        /// Callers wishing to use jpeg_read_icc_profile() must call jpeg_save_markers(cinfo, JPEG_APP0 + 2, 0xFFFF);
+        /// prior to calling jpeg_read_header(). jpeg_read_icc_profile() can be called at
+        /// any point between jpeg_read_header() and jpeg_finish_decompress().
+        #[cfg(feature = "icc_io")]
+        pub fn jpeg_read_icc_profile(cinfo: &mut jpeg_decompress_struct, icc_data_ptr: *mut *mut u8, icc_data_len: *mut c_uint) -> boolean;
+        /// jpeg_write_icc_profile() must be called after calling jpeg_start_compress() and
+        /// before the first call to jpeg_write_scanlines() or jpeg_write_raw_data().  This
+        /// ordering ensures that the APP2 marker(s) will appear after the SOI and JFIF or
+        /// Adobe markers, but before all other data.
+        #[cfg(feature = "icc_io")]
+        pub fn jpeg_write_icc_profile(cinfo: &mut jpeg_compress_struct, icc_data_ptr: *const u8, icc_data_len: c_uint);
+        /// This function provides application programmers with the ability to skip over
+        /// multiple rows in the JPEG image.
+        ///
+        /// Suspending data sources are not supported by this function.  Calling
+        /// jpeg_skip_scanlines() with a suspending data source will result in undefined
+        /// behavior.
+        pub fn jpeg_skip_scanlines(cinfo: &mut jpeg_decompress_struct, num_lines: JDIMENSION) -> JDIMENSION;
+        /// This function provides application programmers with the ability to decompress
+        /// only a portion of each row in the JPEG image.  It must be called after
+        /// jpeg_start_decompress() and before any calls to jpeg_read_scanlines() or
+        /// jpeg_skip_scanlines().
+        /// If the output image is scaled, then xoffset and width are relative to the scaled image dimensions.
+        /// xoffset and width are passed by reference because xoffset must fall on an iMCU
+        /// boundary.  If it doesn't, then it will be moved left to the nearest iMCU
+        /// boundary, and width will be increased accordingly.
+        pub fn jpeg_crop_scanline(cinfo: &mut jpeg_decompress_struct, xoffset: &mut JDIMENSION, width: &mut JDIMENSION);
+        pub fn jpeg_has_multiple_scans(cinfo: &jpeg_decompress_struct) -> boolean;
+        pub fn jpeg_start_output(cinfo: &mut jpeg_decompress_struct, scan_number: c_int) -> boolean;
+        pub fn jpeg_finish_output(cinfo: &mut jpeg_decompress_struct) -> boolean;
+        pub fn jpeg_input_complete(cinfo: &jpeg_decompress_struct) -> boolean;
+        #[deprecated]
+        pub fn jpeg_new_colormap(cinfo: &mut jpeg_decompress_struct);
+        pub fn jpeg_consume_input(cinfo: &mut jpeg_decompress_struct) -> c_int;
+        /// Precalculate JPEG dimensions for current compression parameters
+        #[cfg(feature = "jpeg70_abi")]
+        pub fn jpeg_calc_jpeg_dimensions(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_calc_output_dimensions(cinfo: &mut jpeg_decompress_struct);
+        pub fn jpeg_save_markers(cinfo: &mut jpeg_decompress_struct,
+                             marker_code: c_int,
+                             length_limit: c_uint);
+        pub fn jpeg_set_marker_processor(cinfo: &mut jpeg_decompress_struct,
+                                     marker_code: c_int,
+                                     routine: jpeg_marker_parser_method);
+        pub fn jpeg_read_coefficients(cinfo: &mut jpeg_decompress_struct) -> *mut *mut jvirt_barray_control;
+        pub fn jpeg_write_coefficients(cinfo: &mut jpeg_compress_struct,
+                                   coef_arrays: *mut *mut jvirt_barray_control);
+        pub fn jpeg_copy_critical_parameters(srcinfo: &jpeg_decompress_struct,
+                                         dstinfo: &mut jpeg_compress_struct);
+        #[cfg(feature = "jpeg80_abi")]
+        pub fn jpeg_core_output_dimensions(cinfo: &mut jpeg_decompress_struct);
+        pub fn jpeg_abort_compress(cinfo: &mut jpeg_compress_struct);
+        pub fn jpeg_abort_decompress(cinfo: &mut jpeg_decompress_struct);
+        pub fn jpeg_resync_to_restart(cinfo: &mut jpeg_decompress_struct, desired: c_int) -> boolean;
+        pub fn jpeg_c_bool_param_supported(cinfo: &jpeg_compress_struct,
+                                       param: J_BOOLEAN_PARAM) -> boolean;
+        pub fn jpeg_c_set_bool_param(cinfo: &mut jpeg_compress_struct,
+                                 param: J_BOOLEAN_PARAM, value: boolean);
+        pub fn jpeg_c_get_bool_param(cinfo: &jpeg_compress_struct,
+                                 param: J_BOOLEAN_PARAM) -> boolean;
+        pub fn jpeg_c_float_param_supported(cinfo: &jpeg_compress_struct, param: J_FLOAT_PARAM) -> boolean;
+        pub fn jpeg_abort(cinfo: &mut jpeg_common_struct);
+        pub fn jpeg_destroy(cinfo: &mut jpeg_common_struct);
+        pub fn jpeg_c_set_float_param(cinfo: &mut jpeg_compress_struct, param: J_FLOAT_PARAM, value: f32);
+        pub fn jpeg_c_get_float_param(cinfo: &jpeg_compress_struct, param: J_FLOAT_PARAM) -> f32;
+        pub fn jpeg_c_int_param_supported(cinfo: &jpeg_compress_struct, param: J_INT_PARAM) -> boolean;
+        pub fn jpeg_c_set_int_param(cinfo: &mut jpeg_compress_struct, param: J_INT_PARAM, value: c_int);
+        pub fn jpeg_c_get_int_param(cinfo: &jpeg_compress_struct, param: J_INT_PARAM) -> c_int;
+        pub fn jpeg_set_idct_method_selector(cinfo: &jpeg_compress_struct, param: *const c_void);
+        #[cfg(test)] #[allow(dead_code)] fn jsimd_fdct_ifast(block: *mut DCTELEM);
+    }
 }
 
 #[test]
@@ -882,7 +941,7 @@ fn enum_32bit() {
 #[cfg(feature = "with_simd")]
 fn simd_is_detectable() {
     unsafe {
-        jsimd_can_rgb_ycc();
+        simd::can::jsimd_can_rgb_ycc();
     }
 }
 
@@ -893,7 +952,7 @@ fn simd_works_sse2() {
     struct Aligned([DCTELEM; 64]);
 
     unsafe {
-        assert!(jsimd_can_fdct_ifast() != 0);
+        assert!(simd::can::jsimd_can_fdct_ifast() != 0);
         let mut data = Aligned([0 as DCTELEM; 64]);
         jsimd_fdct_ifast(data.0.as_mut_ptr());
     }
@@ -1606,8 +1665,12 @@ fn all_links() {
         jpeg_CreateDecompress as *const c_void,
         jpeg_destroy_compress as *const c_void,
         jpeg_destroy_decompress as *const c_void,
-        jpeg_stdio_dest as *const c_void,
-        jpeg_stdio_src as *const c_void,
+        #[cfg(not(target_arch = "wasm32"))] {
+            jpeg_stdio_dest as *const c_void
+        },
+        #[cfg(not(target_arch = "wasm32"))] {
+            jpeg_stdio_src as *const c_void
+        },
         jpeg_mem_dest as *const c_void,
         jpeg_mem_src as *const c_void,
         jpeg_set_defaults as *const c_void,
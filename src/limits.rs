@@ -0,0 +1,70 @@
+//! Limits for decoding untrusted files
+use crate::progress::ProgressMgr;
+use crate::*;
+use std::time::Instant;
+
+/// Limits for decompression of untrusted files. `None` means unlimited.
+///
+/// Progressive files can have hundreds of tiny scans, each requiring a pass over the whole image,
+/// so a small file can take minutes to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DecodeLimits {
+    /// Maximum number of scans (`input_scan_number`)
+    pub max_scans: Option<c_int>,
+    /// Maximum `image_width * image_height`
+    pub max_pixels: Option<u64>,
+    /// Time by which decoding must finish
+    pub deadline: Option<Instant>,
+}
+
+/// Which of the `DecodeLimits` has been exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Limit {
+    /// The file has more scans than `max_scans`
+    Scans(c_int),
+    /// The image has more pixels than `max_pixels`
+    Pixels(u64),
+    /// Decoding didn't finish before the `deadline`
+    Deadline,
+}
+
+impl DecodeLimits {
+    /// Checks the image size. Call it after `jpeg_read_header()` and before `jpeg_start_decompress()`,
+    /// which allocates memory for the whole image.
+    pub fn check_header(&self, cinfo: &jpeg_decompress_struct) -> Result<(), Error> {
+        let pixels = u64::from(cinfo.image_width) * u64::from(cinfo.image_height);
+        if self.max_pixels.is_some_and(|max| pixels > max) {
+            return Err(Error::LimitExceeded(Limit::Pixels(pixels)));
+        }
+        Ok(())
+    }
+
+    fn check(&self, cinfo: &jpeg_decompress_struct) -> Result<(), Error> {
+        self.check_header(cinfo)?;
+        if self.max_scans.is_some_and(|max| cinfo.input_scan_number > max) {
+            return Err(Error::LimitExceeded(Limit::Scans(cinfo.input_scan_number)));
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(Error::LimitExceeded(Limit::Deadline));
+        }
+        Ok(())
+    }
+}
+
+/// Enforces the limits from the progress monitor, aborting decompression with `Error::LimitExceeded`.
+/// A progress monitor installed earlier (e.g. by `set_progress()`) keeps being called.
+///
+/// The monitor isn't called before `jpeg_start_decompress()`, so use `DecodeLimits::check_header()` too.
+///
+/// # Safety
+///
+/// `cinfo` must be initialized, and the returned `ProgressMgr` must outlive its use.
+/// The previously installed monitor, if any, must outlive it too.
+/// Decompression must run inside `catch()`.
+#[must_use]
+pub unsafe fn set_decode_limits(cinfo: &mut jpeg_decompress_struct, limits: DecodeLimits) -> Box<ProgressMgr> {
+    ProgressMgr::install(&mut cinfo.common, Box::new(move |common, _| {
+        limits.check(&*(common as *mut jpeg_common_struct).cast::<jpeg_decompress_struct>())
+    }))
+}
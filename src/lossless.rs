@@ -0,0 +1,352 @@
+//! Lossless editing of JPEG files in the DCT domain, based on `transupp.c` (the `jpegtran` library)
+use crate::error::catch;
+use crate::session::{Compress, Decompress};
+use crate::*;
+use std::ptr;
+
+/// Inserts (drops) the JPEG `insert` into the JPEG `base`, with its top left corner at `x`,`y`,
+/// like `jpegtran -drop +X+Y`. The rest of `base` is copied without recompression.
+///
+/// `x` and `y` must be multiples of the iMCU size of `base` (8 or 16 pixels, depending on chroma subsampling),
+/// and `insert` must fit inside `base`. Both images need compatible chroma subsampling
+/// and identical quantization tables, otherwise the inserted area would be garbled.
+///
+/// Markers (EXIF, ICC profile, etc.) are copied from `base`.
+pub fn drop_into(base: &[u8], insert: &[u8], x: u32, y: u32) -> Result<Vec<u8>, Error> {
+    catch(|| unsafe {
+        let mut src = Decompress::new(base);
+        let mut drop = Decompress::new(insert);
+        jcopy_markers_setup(&mut *src, JCOPY_OPTION_JCOPYOPT_ALL);
+        jpeg_read_header(&mut src, true as boolean);
+        jpeg_read_header(&mut drop, true as boolean);
+
+        if x.checked_add(drop.image_width).map_or(true, |r| r > src.image_width) ||
+           y.checked_add(drop.image_height).map_or(true, |b| b > src.image_height) {
+            return Err(Error::InvalidArgument("inserted image doesn't fit in the base image"));
+        }
+        let (imcu_width, imcu_height) = decompress_imcu_size(&src);
+        if x % imcu_width != 0 || y % imcu_height != 0 {
+            return Err(Error::InvalidArgument("drop position must be aligned to the iMCU size of the base image"));
+        }
+        check_same_quant_tables(&src, &drop).map_err(Error::Incompatible)?;
+
+        let mut info: jpeg_transform_info = mem::zeroed();
+        info.transform = JXFORM_CODE_JXFORM_DROP;
+        info.crop = true as boolean;
+        info.crop_xoffset = x;
+        info.crop_xoffset_set = JCROP_CODE_JCROP_POS;
+        info.crop_yoffset = y;
+        info.crop_yoffset_set = JCROP_CODE_JCROP_POS;
+        info.crop_width = drop.image_width;
+        info.crop_width_set = JCROP_CODE_JCROP_POS;
+        info.crop_height = drop.image_height;
+        info.crop_height_set = JCROP_CODE_JCROP_POS;
+        info.drop_ptr = &mut *drop;
+        if jtransform_request_workspace(&mut *src, &mut info) == 0 {
+            return Err(Error::InvalidArgument("transform can't be applied to the base image"));
+        }
+
+        let src_coef_arrays = jpeg_read_coefficients(&mut src);
+        info.drop_coef_arrays = jpeg_read_coefficients(&mut drop);
+
+        let mut dst = Compress::new();
+        jpeg_copy_critical_parameters(&src, &mut dst);
+        let dst_coef_arrays = jtransform_adjust_parameters(&mut *src, &mut *dst, src_coef_arrays, &mut info);
+        jpeg_write_coefficients(&mut dst, dst_coef_arrays);
+        jcopy_markers_execute(&mut *src, &mut *dst, JCOPY_OPTION_JCOPYOPT_ALL);
+        jtransform_execute_transform(&mut *src, &mut *dst, src_coef_arrays, &mut info);
+        Ok(dst.finish())
+    })
+}
+
+/// Coefficients can only be copied between images that use the same quantization
+unsafe fn check_same_quant_tables(a: &jpeg_decompress_struct, b: &jpeg_decompress_struct) -> Result<(), String> {
+    let components = a.num_components.min(b.num_components) as usize;
+    for ci in 0..components {
+        let a_tbl = a.quant_tbl_ptrs.get((*a.comp_info.add(ci)).quant_tbl_no as usize).copied().unwrap_or(ptr::null_mut());
+        let b_tbl = b.quant_tbl_ptrs.get((*b.comp_info.add(ci)).quant_tbl_no as usize).copied().unwrap_or(ptr::null_mut());
+        if a_tbl.is_null() || b_tbl.is_null() || (*a_tbl).quantval != (*b_tbl).quantval {
+            return Err(format!("quantization tables of component {ci} differ"));
+        }
+    }
+    Ok(())
+}
+
+/// Rectangle in pixel coordinates
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Grows the rectangle outwards to the nearest iMCU boundaries, and clips it to the image size.
+    ///
+    /// The right and bottom edges may end on a partial iMCU if it's the edge of the image.
+    #[must_use]
+    pub fn align_to_imcu(&self, imcu_width: u32, imcu_height: u32, image_width: u32, image_height: u32) -> Self {
+        let right = self.x.saturating_add(self.width).min(image_width);
+        let bottom = self.y.saturating_add(self.height).min(image_height);
+        if right <= self.x || bottom <= self.y {
+            return Self { x: self.x.min(image_width), y: self.y.min(image_height), width: 0, height: 0 };
+        }
+        let x = self.x / imcu_width * imcu_width;
+        let y = self.y / imcu_height * imcu_height;
+        let right = round_up(right, imcu_width).min(image_width);
+        let bottom = round_up(bottom, imcu_height).min(image_height);
+        Self { x, y, width: right - x, height: bottom - y }
+    }
+}
+
+fn round_up(val: u32, multiple: u32) -> u32 {
+    val.saturating_add(multiple - 1) / multiple * multiple
+}
+
+/// How `wipe()` fills the redacted areas
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WipeFill {
+    /// Neutral gray (all coefficients zeroed, same as `jpegtran -wipe`)
+    Gray,
+    /// Blurs each 8x8 block to its average color (all AC coefficients zeroed)
+    Blur,
+}
+
+/// Size of the iMCU (minimum coded unit) in pixels. Lossless edits can only be done with this granularity.
+pub fn imcu_size(jpeg: &[u8]) -> Result<(u32, u32), Error> {
+    catch(|| unsafe {
+        let mut src = Decompress::new(jpeg);
+        jpeg_read_header(&mut src, true as boolean);
+        Ok(decompress_imcu_size(&src))
+    })
+}
+
+fn decompress_imcu_size(cinfo: &jpeg_decompress_struct) -> (u32, u32) {
+    if cinfo.num_components == 1 {
+        (DCTSIZE as u32, DCTSIZE as u32)
+    } else {
+        (cinfo.max_h_samp_factor as u32 * DCTSIZE as u32, cinfo.max_v_samp_factor as u32 * DCTSIZE as u32)
+    }
+}
+
+/// Redacts `areas` of the image by editing its DCT coefficients. The rest of the image stays bit-exact.
+///
+/// The areas are expanded to the iMCU grid (see `Rect::align_to_imcu()`),
+/// and the actually covered areas are returned along with the new JPEG file.
+pub fn wipe(jpeg: &[u8], areas: &[Rect], fill: WipeFill) -> Result<(Vec<u8>, Vec<Rect>), Error> {
+    catch(|| unsafe {
+        let mut src = Decompress::new(jpeg);
+        jcopy_markers_setup(&mut *src, JCOPY_OPTION_JCOPYOPT_ALL);
+        jpeg_read_header(&mut src, true as boolean);
+
+        let (imcu_width, imcu_height) = decompress_imcu_size(&src);
+        let covered: Vec<_> = areas.iter()
+            .map(|r| r.align_to_imcu(imcu_width, imcu_height, src.image_width, src.image_height))
+            .collect();
+
+        let coef_arrays = jpeg_read_coefficients(&mut src);
+        let access_virt_barray = (*src.common.mem).access_virt_barray.unwrap();
+
+        for ci in 0..src.num_components as usize {
+            let comp = &*src.comp_info.add(ci);
+            let (h_samp, v_samp) = if src.num_components == 1 { (1, 1) } else { (comp.h_samp_factor as u32, comp.v_samp_factor as u32) };
+            for area in covered.iter().filter(|r| r.width > 0 && r.height > 0) {
+                // iMCU grid to block grid of this component
+                let bx0 = area.x / imcu_width * h_samp;
+                let bx1 = (round_up(area.x + area.width, imcu_width) / imcu_width * h_samp).min(comp.width_in_blocks);
+                let by0 = area.y / imcu_height * v_samp;
+                let by1 = (round_up(area.y + area.height, imcu_height) / imcu_height * v_samp).min(comp.height_in_blocks);
+                for by in by0..by1 {
+                    let row = *access_virt_barray(&mut src.common, *coef_arrays.add(ci), by, 1, true as boolean);
+                    let blocks = std::slice::from_raw_parts_mut(row, comp.width_in_blocks as usize);
+                    for block in &mut blocks[bx0 as usize..bx1 as usize] {
+                        match fill {
+                            WipeFill::Gray => *block = [0; DCTSIZE2],
+                            WipeFill::Blur => block[1..].fill(0),
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut dst = Compress::new();
+        jpeg_copy_critical_parameters(&src, &mut dst);
+        jpeg_write_coefficients(&mut dst, coef_arrays);
+        jcopy_markers_execute(&mut *src, &mut *dst, JCOPY_OPTION_JCOPYOPT_ALL);
+        Ok((dst.finish(), covered))
+    })
+}
+
+/// Joins a grid of JPEG tiles into one JPEG without recompression.
+///
+/// `tiles` are in row-major order, `columns` per row. All tiles in a row must have the same height,
+/// and all tiles in a column the same width. Widths and heights of all tiles, except the last column and row,
+/// must be multiples of the iMCU size (see `imcu_size()`). All tiles must have the same components,
+/// chroma subsampling and quantization tables, which is typically the case for tiles saved by the same encoder.
+///
+/// ICC profile of the first tile is copied to the output.
+pub fn stitch(tiles: &[&[u8]], columns: usize) -> Result<Vec<u8>, Error> {
+    if columns == 0 || tiles.is_empty() || tiles.len() % columns != 0 {
+        return Err(Error::InvalidArgument("number of tiles must be a non-zero multiple of the number of columns"));
+    }
+    let rows = tiles.len() / columns;
+
+    catch(|| unsafe {
+        let mut srcs: Vec<_> = tiles.iter().map(|&t| Decompress::new(t)).collect();
+        jcopy_markers_setup(&mut *srcs[0], JCOPY_OPTION_JCOPYOPT_ICC);
+        for src in &mut srcs {
+            jpeg_read_header(src, true as boolean);
+        }
+
+        let (imcu_width, imcu_height) = decompress_imcu_size(&srcs[0]);
+        let col_widths: Vec<_> = srcs[..columns].iter().map(|s| s.image_width).collect();
+        let row_heights: Vec<_> = srcs.iter().step_by(columns).map(|s| s.image_height).collect();
+        for (i, src) in srcs.iter().enumerate() {
+            let (col, row) = (i % columns, i / columns);
+            if src.image_width != col_widths[col] || src.image_height != row_heights[row] {
+                return Err(Error::Incompatible(format!("tile {i} is {}x{}, but its column is {} wide and row is {} high",
+                    src.image_width, src.image_height, col_widths[col], row_heights[row])));
+            }
+            if (col + 1 < columns && src.image_width % imcu_width != 0) || (row + 1 < rows && src.image_height % imcu_height != 0) {
+                return Err(Error::Incompatible(format!("tile {i} is {}x{}, which is not a multiple of the {imcu_width}x{imcu_height} iMCU size",
+                    src.image_width, src.image_height)));
+            }
+            check_same_layout(&srcs[0], src)
+                .and_then(|()| check_same_quant_tables(&srcs[0], src))
+                .map_err(|e| Error::Incompatible(format!("tile {i}: {e}")))?;
+        }
+
+        let src_coef_arrays: Vec<_> = srcs.iter_mut().map(|s| jpeg_read_coefficients(s)).collect();
+
+        let mut dst = Compress::new();
+        jpeg_copy_critical_parameters(&srcs[0], &mut dst);
+        dst.image_width = col_widths.iter().try_fold(0u32, |sum, &w| sum.checked_add(w)).ok_or(Error::InvalidArgument("image too large"))?;
+        dst.image_height = row_heights.iter().try_fold(0u32, |sum, &h| sum.checked_add(h)).ok_or(Error::InvalidArgument("image too large"))?;
+
+        let mem = &*dst.common.mem;
+        let num_components = dst.num_components as usize;
+        // `max_h_samp_factor` isn't set until compression starts
+        let comps = std::slice::from_raw_parts(dst.comp_info, num_components);
+        let max_h = comps.iter().map(|c| c.h_samp_factor as u32).max().unwrap_or(1);
+        let max_v = comps.iter().map(|c| c.v_samp_factor as u32).max().unwrap_or(1);
+        let mut dst_coef_arrays = Vec::with_capacity(num_components);
+        for ci in 0..num_components {
+            let comp = &*dst.comp_info.add(ci);
+            let (h_samp, v_samp) = (comp.h_samp_factor as u32, comp.v_samp_factor as u32);
+            let width_in_blocks = size_in_blocks(dst.image_width, h_samp, max_h)?;
+            let height_in_blocks = size_in_blocks(dst.image_height, v_samp, max_v)?;
+            // the compressor accesses a whole iMCU row at a time
+            dst_coef_arrays.push(mem.request_virt_barray.unwrap()(&mut dst.common, JPOOL_IMAGE, true as boolean,
+                width_in_blocks, height_in_blocks, v_samp));
+        }
+        mem.realize_virt_arrays.unwrap()(&mut dst.common);
+
+        let mut y = 0;
+        for (row, &tile_height) in row_heights.iter().enumerate() {
+            let mut x = 0;
+            for (col, &tile_width) in col_widths.iter().enumerate() {
+                let i = row * columns + col;
+                let src = &mut srcs[i];
+                for (ci, &dst_array) in dst_coef_arrays.iter().enumerate() {
+                    let comp = &*src.comp_info.add(ci);
+                    let (h_samp, v_samp) = (comp.h_samp_factor as u32, comp.v_samp_factor as u32);
+                    // offsets are smaller than the output size, so they fit in u32 if `size_in_blocks` did
+                    let bx = (u64::from(x) * u64::from(h_samp) / u64::from(max_h * DCTSIZE as u32)) as u32;
+                    let by = (u64::from(y) * u64::from(v_samp) / u64::from(max_v * DCTSIZE as u32)) as u32;
+                    for src_by in 0..comp.height_in_blocks {
+                        let src_row = *mem.access_virt_barray.unwrap()(&mut src.common, *src_coef_arrays[i].add(ci), src_by, 1, false as boolean);
+                        let dst_row = *mem.access_virt_barray.unwrap()(&mut dst.common, dst_array, by + src_by, 1, true as boolean);
+                        ptr::copy_nonoverlapping(src_row, dst_row.add(bx as usize), comp.width_in_blocks as usize);
+                    }
+                }
+                x += tile_width;
+            }
+            y += tile_height;
+        }
+
+        jpeg_write_coefficients(&mut dst, dst_coef_arrays.as_mut_ptr());
+        jcopy_markers_execute(&mut *srcs[0], &mut *dst, JCOPY_OPTION_JCOPYOPT_ICC);
+        Ok(dst.finish())
+    })
+}
+
+/// Number of DCT blocks of a component covering `pixels`, rounded up to the component's sampling factor
+fn size_in_blocks(pixels: u32, samp: u32, max_samp: u32) -> Result<JDIMENSION, Error> {
+    let per_block = u64::from(max_samp * DCTSIZE as u32);
+    let blocks = (u64::from(pixels) * u64::from(samp) + per_block - 1) / per_block;
+    let blocks = (blocks + u64::from(samp) - 1) / u64::from(samp) * u64::from(samp);
+    blocks.try_into().map_err(|_| Error::InvalidArgument("image too large"))
+}
+
+/// Components and their subsampling must be the same to copy coefficients 1:1
+unsafe fn check_same_layout(a: &jpeg_decompress_struct, b: &jpeg_decompress_struct) -> Result<(), String> {
+    if a.num_components != b.num_components || a.jpeg_color_space != b.jpeg_color_space {
+        return Err(format!("{} components in {:?} instead of {} in {:?}",
+            b.num_components, b.jpeg_color_space, a.num_components, a.jpeg_color_space));
+    }
+    for ci in 0..a.num_components as usize {
+        let (a_comp, b_comp) = (&*a.comp_info.add(ci), &*b.comp_info.add(ci));
+        if (a_comp.h_samp_factor, a_comp.v_samp_factor) != (b_comp.h_samp_factor, b_comp.v_samp_factor) {
+            return Err(format!("component {ci} has {}x{} sampling instead of {}x{}",
+                b_comp.h_samp_factor, b_comp.v_samp_factor, a_comp.h_samp_factor, a_comp.v_samp_factor));
+        }
+    }
+    Ok(())
+}
+
+/// Cuts the image into a grid of tiles without recompression (like `jpegtran -crop` for every tile).
+///
+/// `tile_width` and `tile_height` must be multiples of the iMCU size (see `imcu_size()`).
+/// Tiles in the last column and row are smaller if the image size isn't a multiple of the tile size.
+/// Returns tiles in row-major order, with their position in the source image.
+///
+/// All tiles are kept in memory, so this needs about twice as much memory as the coefficients of the image.
+/// ICC profile is copied to every tile.
+pub fn split(jpeg: &[u8], tile_width: u32, tile_height: u32) -> Result<Vec<(Rect, Vec<u8>)>, Error> {
+    catch(|| unsafe {
+        let mut src = Decompress::new(jpeg);
+        jcopy_markers_setup(&mut *src, JCOPY_OPTION_JCOPYOPT_ICC);
+        jpeg_read_header(&mut src, true as boolean);
+
+        let (imcu_width, imcu_height) = decompress_imcu_size(&src);
+        if tile_width == 0 || tile_height == 0 || tile_width % imcu_width != 0 || tile_height % imcu_height != 0 {
+            return Err(Error::InvalidArgument("tile size must be a non-zero multiple of the iMCU size"));
+        }
+
+        let (image_width, image_height) = (src.image_width, src.image_height);
+        let mut tiles = Vec::new();
+        for y in (0..image_height).step_by(tile_height as usize) {
+            for x in (0..image_width).step_by(tile_width as usize) {
+                let rect = Rect { x, y, width: tile_width.min(image_width - x), height: tile_height.min(image_height - y) };
+                let mut info: jpeg_transform_info = mem::zeroed();
+                info.transform = JXFORM_CODE_JXFORM_NONE;
+                info.crop = true as boolean;
+                info.crop_xoffset = rect.x;
+                info.crop_xoffset_set = JCROP_CODE_JCROP_POS;
+                info.crop_yoffset = rect.y;
+                info.crop_yoffset_set = JCROP_CODE_JCROP_POS;
+                info.crop_width = rect.width;
+                info.crop_width_set = JCROP_CODE_JCROP_POS;
+                info.crop_height = rect.height;
+                info.crop_height_set = JCROP_CODE_JCROP_POS;
+                // Workspaces must be requested before reading coefficients
+                if jtransform_request_workspace(&mut *src, &mut info) == 0 {
+                    return Err(Error::InvalidArgument("tile can't be cropped from the image"));
+                }
+                tiles.push((rect, info));
+            }
+        }
+
+        let src_coef_arrays = jpeg_read_coefficients(&mut src);
+        tiles.into_iter().map(|(rect, mut info)| {
+            let mut dst = Compress::new();
+            jpeg_copy_critical_parameters(&src, &mut dst);
+            let dst_coef_arrays = jtransform_adjust_parameters(&mut *src, &mut *dst, src_coef_arrays, &mut info);
+            jpeg_write_coefficients(&mut dst, dst_coef_arrays);
+            jcopy_markers_execute(&mut *src, &mut *dst, JCOPY_OPTION_JCOPYOPT_ICC);
+            jtransform_execute_transform(&mut *src, &mut *dst, src_coef_arrays, &mut info);
+            Ok((rect, dst.finish()))
+        }).collect()
+    })
+}
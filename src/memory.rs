@@ -0,0 +1,214 @@
+//! Memory limit enforced by wrapping the methods of `jpeg_memory_mgr`
+use crate::error::catch;
+use crate::*;
+use std::panic;
+use std::ptr;
+
+/// Replaces `cinfo.mem`, wrapping libjpeg's memory manager. Allocated in its `JPOOL_PERMANENT`.
+#[repr(C)]
+struct BudgetMgr {
+    iface: jpeg_memory_mgr,
+    /// libjpeg's memory manager. Its methods expect to find it in `cinfo.mem`.
+    inner: *mut jpeg_memory_mgr,
+    limit: usize,
+    /// Bytes requested from `JPOOL_PERMANENT` and `JPOOL_IMAGE`
+    used: [usize; 2],
+    /// Part of `used` requested for virtual arrays, which are always in `JPOOL_IMAGE`
+    virtual_arrays: usize,
+}
+
+/// Points `cinfo.mem` at libjpeg's memory manager while its methods run, and back at the wrapper afterwards,
+/// even if they unwind
+struct Inner<'a> {
+    cinfo: &'a mut jpeg_common_struct,
+    wrapper: *mut jpeg_memory_mgr,
+    methods: jpeg_memory_mgr,
+}
+
+impl<'a> Inner<'a> {
+    unsafe fn enter(cinfo: &'a mut jpeg_common_struct) -> Self {
+        let wrapper = cinfo.mem;
+        let budget = &*wrapper.cast::<BudgetMgr>();
+        let inner = &mut *budget.inner;
+        // these can be set by the application on the wrapper
+        inner.max_memory_to_use = budget.iface.max_memory_to_use;
+        inner.max_alloc_chunk = budget.iface.max_alloc_chunk;
+        cinfo.mem = inner;
+        Self { cinfo, wrapper, methods: jpeg_memory_mgr { ..*inner } }
+    }
+
+    /// For methods that free the wrapper too
+    fn leave(self) {
+        mem::forget(self);
+    }
+}
+
+impl Drop for Inner<'_> {
+    fn drop(&mut self) {
+        self.cinfo.mem = self.wrapper;
+    }
+}
+
+/// The wrapper installed in `cinfo.mem`. Unwinds with `Error::InvalidArgument` if something else replaced it.
+unsafe fn budget(cinfo: &jpeg_common_struct) -> *mut BudgetMgr {
+    if !is_wrapper(cinfo.mem) {
+        panic::resume_unwind(Box::new(Error::InvalidArgument("memory manager with a limit has been replaced")));
+    }
+    cinfo.mem.cast()
+}
+
+unsafe fn is_wrapper(mem: *const jpeg_memory_mgr) -> bool {
+    // `alloc_small` has a unique body, so it can't be merged with another function
+    let ours: unsafe extern "C-unwind" fn(&mut jpeg_common_struct, c_int, usize) -> *mut c_void = alloc_small;
+    !mem.is_null() && (*mem).alloc_small.map(|f| f as usize) == Some(ours as usize)
+}
+
+/// Limits memory used by `cinfo` to `limit` bytes, including whole-image virtual arrays
+/// (used by progressive decoding, `jpeg_read_coefficients` and mozjpeg's scan optimization).
+///
+/// Allocations beyond the limit fail with `Error::OutOfMemory`, which unwinds to the nearest `catch()`.
+/// The limit is also set as `max_memory_to_use`. It can be changed by calling this function again.
+///
+/// Sizes of libjpeg's own bookkeeping aren't counted, so actual use can be slightly higher.
+/// With the `backing_store` feature, parts of virtual arrays that don't fit are kept in temporary files instead.
+///
+/// # Safety
+///
+/// Must be called after `jpeg_create_compress`/`jpeg_create_decompress`.
+/// `cinfo.mem` must not be replaced afterwards.
+pub unsafe fn set_memory_limit(cinfo: &mut jpeg_common_struct, limit: usize) {
+    let max_memory_to_use = limit.try_into().unwrap_or(c_long::MAX);
+    if is_wrapper(cinfo.mem) {
+        let budget = &mut *budget(cinfo);
+        budget.limit = limit;
+        budget.iface.max_memory_to_use = max_memory_to_use;
+        return;
+    }
+    let inner = cinfo.mem;
+    (*inner).max_memory_to_use = max_memory_to_use;
+    // freed along with the rest of the permanent pool by `self_destruct`
+    let budget = ((*inner).alloc_small.unwrap())(cinfo, JPOOL_PERMANENT, mem::size_of::<BudgetMgr>()).cast::<BudgetMgr>();
+    ptr::write(budget, BudgetMgr {
+        iface: jpeg_memory_mgr {
+            alloc_small: Some(alloc_small),
+            alloc_large: Some(alloc_large),
+            alloc_sarray: Some(alloc_sarray),
+            alloc_barray: Some(alloc_barray),
+            request_virt_sarray: Some(request_virt_sarray),
+            request_virt_barray: Some(request_virt_barray),
+            realize_virt_arrays: Some(realize_virt_arrays),
+            free_pool: Some(free_pool),
+            self_destruct: Some(self_destruct),
+            ..*inner
+        },
+        inner,
+        limit,
+        used: [0; 2],
+        virtual_arrays: 0,
+    });
+    cinfo.mem = &mut (*budget).iface;
+}
+
+/// Counts `size` bytes against the budget, or unwinds with `Error::OutOfMemory`
+unsafe fn reserve(cinfo: &jpeg_common_struct, pool_id: c_int, size: usize, is_virtual: bool) {
+    // With a backing store, libjpeg keeps in memory only as much of virtual arrays as `max_memory_to_use` allows
+    let counted = if is_virtual && cfg!(feature = "backing_store") { 0 } else { size };
+    let budget = &mut *budget(cinfo);
+    let total = budget.used[0].saturating_add(budget.used[1]).saturating_add(counted);
+    if total > budget.limit {
+        panic::resume_unwind(Box::new(Error::OutOfMemory { requested: size, limit: budget.limit }));
+    }
+    // Invalid pool ids are left for the original method to report
+    if let Some(used) = budget.used.get_mut(pool_id as usize) {
+        *used += counted;
+        if is_virtual {
+            budget.virtual_arrays += size;
+        }
+    }
+}
+
+fn sarray_size(samplesperrow: JDIMENSION, numrows: JDIMENSION) -> usize {
+    let row = (samplesperrow as usize).saturating_mul(mem::size_of::<JSAMPLE>()) + mem::size_of::<JSAMPROW>();
+    row.saturating_mul(numrows as usize)
+}
+
+fn barray_size(blocksperrow: JDIMENSION, numrows: JDIMENSION) -> usize {
+    let row = (blocksperrow as usize).saturating_mul(mem::size_of::<JBLOCK>()) + mem::size_of::<JBLOCKROW>();
+    row.saturating_mul(numrows as usize)
+}
+
+unsafe extern "C-unwind" fn alloc_small(cinfo: &mut jpeg_common_struct, pool_id: c_int, sizeofobject: usize) -> *mut c_void {
+    reserve(cinfo, pool_id, sizeofobject, false);
+    let inner = Inner::enter(cinfo);
+    (inner.methods.alloc_small.unwrap())(inner.cinfo, pool_id, sizeofobject)
+}
+
+unsafe extern "C-unwind" fn alloc_large(cinfo: &mut jpeg_common_struct, pool_id: c_int, sizeofobject: usize) -> *mut c_void {
+    reserve(cinfo, pool_id, sizeofobject, false);
+    let inner = Inner::enter(cinfo);
+    (inner.methods.alloc_large.unwrap())(inner.cinfo, pool_id, sizeofobject)
+}
+
+unsafe extern "C-unwind" fn alloc_sarray(cinfo: &mut jpeg_common_struct, pool_id: c_int, samplesperrow: JDIMENSION, numrows: JDIMENSION) -> JSAMPARRAY_MUT {
+    reserve(cinfo, pool_id, sarray_size(samplesperrow, numrows), false);
+    let inner = Inner::enter(cinfo);
+    (inner.methods.alloc_sarray.unwrap())(inner.cinfo, pool_id, samplesperrow, numrows)
+}
+
+unsafe extern "C-unwind" fn alloc_barray(cinfo: &mut jpeg_common_struct, pool_id: c_int, blocksperrow: JDIMENSION, numrows: JDIMENSION) -> JBLOCKARRAY {
+    reserve(cinfo, pool_id, barray_size(blocksperrow, numrows), false);
+    let inner = Inner::enter(cinfo);
+    (inner.methods.alloc_barray.unwrap())(inner.cinfo, pool_id, blocksperrow, numrows)
+}
+
+/// Virtual arrays are allocated later, by `realize_virt_arrays`, but this is the last point where their size is known
+unsafe extern "C-unwind" fn request_virt_sarray(cinfo: &mut jpeg_common_struct, pool_id: c_int, pre_zero: boolean, samplesperrow: JDIMENSION, numrows: JDIMENSION, maxaccess: JDIMENSION) -> *mut jvirt_sarray_control {
+    reserve(cinfo, pool_id, sarray_size(samplesperrow, numrows), true);
+    let inner = Inner::enter(cinfo);
+    (inner.methods.request_virt_sarray.unwrap())(inner.cinfo, pool_id, pre_zero, samplesperrow, numrows, maxaccess)
+}
+
+unsafe extern "C-unwind" fn request_virt_barray(cinfo: &mut jpeg_common_struct, pool_id: c_int, pre_zero: boolean, blocksperrow: JDIMENSION, numrows: JDIMENSION, maxaccess: JDIMENSION) -> *mut jvirt_barray_control {
+    reserve(cinfo, pool_id, barray_size(blocksperrow, numrows), true);
+    let inner = Inner::enter(cinfo);
+    (inner.methods.request_virt_barray.unwrap())(inner.cinfo, pool_id, pre_zero, blocksperrow, numrows, maxaccess)
+}
+
+/// libjpeg counts its bookkeeping too, so it can run out of `max_memory_to_use` before the budget does
+unsafe extern "C-unwind" fn realize_virt_arrays(cinfo: &mut jpeg_common_struct) {
+    let res = catch(|| {
+        let inner = Inner::enter(cinfo);
+        (inner.methods.realize_virt_arrays.unwrap())(inner.cinfo);
+        Ok(())
+    });
+    match res {
+        Ok(()) => {},
+        Err(Error::Libjpeg { code: JERR_NO_BACKING_STORE, .. }) => {
+            let budget = &*budget(cinfo);
+            panic::resume_unwind(Box::new(Error::OutOfMemory { requested: budget.virtual_arrays, limit: budget.limit }));
+        },
+        Err(err) => panic::resume_unwind(Box::new(err)),
+    }
+}
+
+unsafe extern "C-unwind" fn free_pool(cinfo: &mut jpeg_common_struct, pool_id: c_int) {
+    let budget = &mut *budget(cinfo);
+    if let Some(used) = budget.used.get_mut(pool_id as usize) {
+        *used = 0;
+    }
+    if pool_id == JPOOL_IMAGE {
+        budget.virtual_arrays = 0;
+    }
+    let inner = Inner::enter(cinfo);
+    (inner.methods.free_pool.unwrap())(inner.cinfo, pool_id);
+    if pool_id == JPOOL_PERMANENT {
+        // the wrapper was in that pool, so libjpeg's manager takes over without a limit
+        inner.leave();
+    }
+}
+
+unsafe extern "C-unwind" fn self_destruct(cinfo: &mut jpeg_common_struct) {
+    // frees the wrapper too, and sets `cinfo.mem` to NULL
+    cinfo.mem = (*budget(cinfo)).inner;
+    ((*cinfo.mem).self_destruct.unwrap())(cinfo);
+}
@@ -0,0 +1,184 @@
+//! Formatting of libjpeg messages in Rust, without the 80-byte limit of `format_message`
+use crate::*;
+use std::ffi::CStr;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Pointer tables made for `set_addon_messages()`, by address of the Rust table.
+/// They're kept forever, like the tables they point to.
+static ADDON_TABLES: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+
+impl msg_parm_union {
+    /// Integer parameters, used by most messages
+    #[must_use]
+    pub fn int_params(&self) -> [c_int; 8] {
+        let mut ints = [0; 8];
+        for (int, &word) in ints.iter_mut().zip(&self._bindgen_data_) {
+            *int = word as c_int;
+        }
+        ints
+    }
+
+    /// String parameter, used by messages with `%s`, without the terminating zero
+    #[must_use]
+    pub fn str_param(&self) -> &[u8] {
+        // the union is 80 bytes, same as `s`
+        let bytes = unsafe { &*self._bindgen_data_.as_ptr().cast::<[u8; 80]>() };
+        let len = bytes.iter().position(|&c| c == 0).unwrap_or(bytes.len());
+        &bytes[..len]
+    }
+}
+
+impl jpeg_error_mgr {
+    /// Formats the current `msg_code` and `msg_parm`, like `format_message` does.
+    ///
+    /// Looks up the text in `jpeg_message_table` and the addon table, if any.
+    /// Unknown codes give the same "Bogus message code" text as libjpeg.
+    #[must_use]
+    pub fn message(&self) -> String {
+        let code = self.msg_code;
+        let mut params = self.msg_parm.int_params();
+        // 0 is the text for bogus codes
+        let text = self.message_text(code).filter(|_| code != 0).unwrap_or_else(|| {
+            params[0] = code;
+            self.message_text(0).unwrap_or_default()
+        });
+        format_printf(&text, &params, self.msg_parm.str_param())
+    }
+
+    /// Registers application-defined messages, so that codes from `first_code` to `first_code + messages.len() - 1`
+    /// can be used with `jpeg_common_struct::error_exit()` and `warn()`, and get formatted like the built-in ones.
+    ///
+    /// Replaces any previous addon table. `first_code` must be greater than `JMSG_LASTMSGCODE`.
+    /// Messages use `printf` syntax, e.g. `Bad chunk %d`. Only integer parameters are supported.
+    pub fn set_addon_messages(&mut self, first_code: J_MESSAGE_CODE, messages: &'static [&'static CStr]) {
+        assert!(first_code > JMSG_LASTMSGCODE, "addon codes overlap libjpeg's");
+        assert!(!messages.is_empty());
+        let key = messages.as_ptr() as usize;
+        let mut tables = ADDON_TABLES.lock().unwrap_or_else(|e| e.into_inner());
+        let table = if let Some(&(_, table)) = tables.iter().find(|&&(k, _)| k == key) {
+            table
+        } else {
+            let table: Box<[*const i8]> = messages.iter().map(|m| m.as_ptr().cast()).collect();
+            let table = Box::leak(table).as_ptr() as usize;
+            tables.push((key, table));
+            table
+        };
+        self.addon_message_table = table as *const *const i8;
+        self.first_addon_message = first_code;
+        self.last_addon_message = first_code + messages.len() as c_int - 1;
+    }
+
+    fn message_text(&self, code: c_int) -> Option<String> {
+        let text = if code >= 0 && code <= self.last_jpeg_message && !self.jpeg_message_table.is_null() {
+            unsafe { *self.jpeg_message_table.add(code as usize) }
+        } else if !self.addon_message_table.is_null() && code >= self.first_addon_message && code <= self.last_addon_message {
+            unsafe { *self.addon_message_table.add((code - self.first_addon_message) as usize) }
+        } else {
+            return None;
+        };
+        if text.is_null() {
+            return None;
+        }
+        Some(unsafe { CStr::from_ptr(text.cast()) }.to_string_lossy().into_owned())
+    }
+}
+
+impl jpeg_common_struct {
+    /// Aborts with the given code, like the `ERREXIT` macros in C. Parameters beyond 8 are ignored.
+    ///
+    /// Use it in Rust callbacks, such as source managers, to report errors the same way as libjpeg does.
+    ///
+    /// # Safety
+    ///
+    /// `err` must point to a valid error manager. The `error_exit` handler may unwind or `longjmp` out of this call.
+    pub unsafe fn error_exit(&mut self, code: J_MESSAGE_CODE, params: &[c_int]) -> ! {
+        self.set_message(code, params);
+        if let Some(error_exit) = (*self.err).error_exit {
+            error_exit(self);
+        }
+        panic!("error_exit returned");
+    }
+
+    /// Reports a warning with the given code, like the `WARNMS` macros in C
+    ///
+    /// # Safety
+    ///
+    /// `err` must point to a valid error manager
+    pub unsafe fn warn(&mut self, code: J_MESSAGE_CODE, params: &[c_int]) {
+        self.set_message(code, params);
+        if let Some(emit_message) = (*self.err).emit_message {
+            emit_message(self, -1);
+        }
+    }
+
+    unsafe fn set_message(&mut self, code: J_MESSAGE_CODE, params: &[c_int]) {
+        let err = &mut *self.err;
+        err.msg_code = code;
+        // same layout as `i`, see `int_params()`
+        for (word, &param) in err.msg_parm._bindgen_data_.iter_mut().take(8).zip(params) {
+            *word = param as u32;
+        }
+    }
+}
+
+/// Subset of `printf` used by libjpeg's messages. Messages with `%s` have no other parameters.
+fn format_printf(text: &str, ints: &[c_int; 8], string: &[u8]) -> String {
+    let mut out = String::with_capacity(text.len() + 16);
+    let mut ints = ints.iter().copied();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            out.push(ch);
+            continue;
+        }
+        let mut zero_pad = false;
+        let mut left = false;
+        loop {
+            match chars.peek() {
+                Some('0') => zero_pad = true,
+                Some('-') => left = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        let mut width = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            width = width * 10 + digit as usize;
+            chars.next();
+        }
+        while chars.peek() == Some(&'l') {
+            chars.next();
+        }
+        let formatted = match chars.next() {
+            Some('%') => "%".into(),
+            Some('s') => String::from_utf8_lossy(string).into_owned(),
+            Some('d' | 'i') => ints.next().unwrap_or(0).to_string(),
+            Some('u') => (ints.next().unwrap_or(0) as c_uint).to_string(),
+            Some('x') => format!("{:x}", ints.next().unwrap_or(0) as c_uint),
+            Some('X') => format!("{:X}", ints.next().unwrap_or(0) as c_uint),
+            Some('c') => char::from(ints.next().unwrap_or(0) as u8).into(),
+            Some(other) => format!("%{other}"),
+            None => "%".into(),
+        };
+        let pad = width.saturating_sub(formatted.len());
+        if left {
+            out.push_str(&formatted);
+            out.extend(std::iter::repeat(' ').take(pad));
+        } else if zero_pad && !formatted.starts_with('-') {
+            out.extend(std::iter::repeat('0').take(pad));
+            out.push_str(&formatted);
+        } else {
+            let _ = write!(out, "{formatted:>width$}");
+        }
+    }
+    out
+}
+
+#[test]
+fn printf_subset() {
+    let ints = [7, -3, 255, 0xFF, 65, 0, 0, 0];
+    assert_eq!("a7 -3 0xff 0x00FF A 100%", format_printf("a%d %d 0x%02x 0x%04X %c 100%%", &ints, b""));
+    assert_eq!("[  7][-3  ][255]", format_printf("[%3d][%-4d][%03u]", &ints, b""));
+    assert_eq!("file x.icc: bad", format_printf("file %s: bad", &ints, b"x.icc"));
+}
@@ -0,0 +1,135 @@
+//! 12-bit build of the library, with the `precision12` feature.
+//!
+//! libjpeg's sample size is fixed at compile time, so this is a second copy of the library built with
+//! `BITS_IN_JSAMPLE 12`, and with its symbols prefixed with `mozjpeg12_`. The regular 8-bit functions
+//! reject 12-bit JPEGs with `JERR_BAD_PRECISION`.
+//!
+//! The structs are the same as in the 8-bit API, but a struct must be used only with functions from
+//! the module that created it. Samples are `J12SAMPLE`s in the range `0..=MAXJ12SAMPLE`.
+//! Error managers are shared, so `jpeg_std_error` and `ErrorMgr` work with both.
+//!
+//! There's no SIMD for 12-bit samples, and the `jpegtran` and `turbojpeg_api` functions are 8-bit only.
+//! Overshoot deringing and DC trellis quantization are always disabled.
+//!
+//! ```rust,ignore
+//! use mozjpeg_sys::precision12 as jpeg12;
+//! jpeg12::jpeg_create_compress(&mut cinfo);
+//! ```
+use crate::*;
+use std::mem;
+
+pub use crate::jpeg_std_error;
+
+/// Sample with 12 significant bits (`short` in C)
+pub type J12SAMPLE = u16;
+pub const MAXJ12SAMPLE: J12SAMPLE = 4095;
+pub const CENTERJ12SAMPLE: J12SAMPLE = 2048;
+
+pub type J12SAMPROW = *const J12SAMPLE;
+pub type J12SAMPROW_MUT = *mut J12SAMPLE;
+
+pub type J12SAMPARRAY = *const J12SAMPROW;
+pub type J12SAMPARRAY_MUT = *mut J12SAMPROW_MUT;
+
+pub type J12SAMPIMAGE = *const J12SAMPARRAY;
+pub type J12SAMPIMAGE_MUT = *mut J12SAMPARRAY_MUT;
+
+/// # Safety
+///
+/// `dinfo` must point to a zeroed struct with `common.err` set
+pub unsafe fn jpeg_create_decompress(dinfo: *mut jpeg_decompress_struct) {
+    jpeg_CreateDecompress(dinfo, JPEG_LIB_VERSION, mem::size_of::<jpeg_decompress_struct>());
+}
+
+/// # Safety
+///
+/// `cinfo` must point to a zeroed struct with `common.err` set
+pub unsafe fn jpeg_create_compress(cinfo: *mut jpeg_compress_struct) {
+    jpeg_CreateCompress(cinfo, JPEG_LIB_VERSION, mem::size_of::<jpeg_compress_struct>());
+}
+
+/// Starts compression, after disabling overshoot deringing and DC trellis quantization,
+/// which MozJPEG implements only for 8-bit samples (they corrupt 12-bit images)
+///
+/// # Safety
+///
+/// `cinfo` must have been created with `precision12::jpeg_create_compress()`
+pub unsafe fn jpeg_start_compress(cinfo: &mut jpeg_compress_struct, write_all_tables: boolean) {
+    jpeg_c_set_bool_param(cinfo, JBOOLEAN_OVERSHOOT_DERINGING, false as boolean);
+    jpeg_c_set_bool_param(cinfo, JBOOLEAN_TRELLIS_QUANT_DC, false as boolean);
+    start_compress(cinfo, write_all_tables);
+}
+
+extern "C-unwind" {
+    #[link_name = "mozjpeg12_jpeg_start_compress"]
+    fn start_compress(cinfo: &mut jpeg_compress_struct, write_all_tables: boolean);
+}
+
+/// Links each function to its `mozjpeg12_`-prefixed symbol
+macro_rules! precision12 {
+    ($($(#[$attr:meta])* pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*) => {
+        extern "C-unwind" {
+            $(
+                $(#[$attr])*
+                #[link_name = concat!("mozjpeg12_", stringify!($name))]
+                pub fn $name($($arg: $ty),*) $(-> $ret)?;
+            )*
+        }
+    };
+}
+
+precision12! {
+    pub fn jpeg_CreateCompress(cinfo: *mut jpeg_compress_struct, version: c_int, structsize: usize);
+    pub fn jpeg_CreateDecompress(cinfo: *mut jpeg_decompress_struct, version: c_int, structsize: usize);
+    pub fn jpeg_destroy_compress(cinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_destroy_decompress(cinfo: &mut jpeg_decompress_struct);
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn jpeg_stdio_dest(cinfo: &mut jpeg_compress_struct, outfile: *mut FILE);
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn jpeg_stdio_src(cinfo: &mut jpeg_decompress_struct, infile: *mut FILE);
+    pub fn jpeg_mem_dest(cinfo: &mut jpeg_compress_struct, outbuffer: *mut *mut u8, outsize: *mut c_ulong);
+    pub fn jpeg_mem_src(cinfo: &mut jpeg_decompress_struct, inbuffer: *const u8, insize: c_ulong);
+    pub fn jpeg_set_defaults(cinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_set_colorspace(cinfo: &mut jpeg_compress_struct, colorspace: J_COLOR_SPACE);
+    pub fn jpeg_default_colorspace(cinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_set_quality(cinfo: &mut jpeg_compress_struct, quality: c_int, force_baseline: boolean);
+    pub fn jpeg_set_linear_quality(cinfo: &mut jpeg_compress_struct, scale_factor: c_int, force_baseline: boolean);
+    pub fn jpeg_add_quant_table(cinfo: &mut jpeg_compress_struct, which_tbl: c_int, basic_table: *const c_uint, scale_factor: c_int, force_baseline: boolean);
+    pub fn jpeg_simple_progression(cinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_suppress_tables(cinfo: &mut jpeg_compress_struct, suppress: boolean);
+    pub fn jpeg_write_scanlines(cinfo: &mut jpeg_compress_struct, scanlines: J12SAMPARRAY, num_lines: JDIMENSION) -> JDIMENSION;
+    pub fn jpeg_finish_compress(cinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_write_raw_data(cinfo: &mut jpeg_compress_struct, data: J12SAMPIMAGE, num_lines: JDIMENSION) -> JDIMENSION;
+    pub fn jpeg_write_marker(cinfo: &mut jpeg_compress_struct, marker: c_int, dataptr: *const u8, datalen: c_uint);
+    pub fn jpeg_write_tables(cinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_read_header(cinfo: &mut jpeg_decompress_struct, require_image: boolean) -> c_int;
+    pub fn jpeg_start_decompress(cinfo: &mut jpeg_decompress_struct) -> boolean;
+    pub fn jpeg_read_scanlines(cinfo: &mut jpeg_decompress_struct, scanlines: J12SAMPARRAY_MUT, max_lines: JDIMENSION) -> JDIMENSION;
+    pub fn jpeg_finish_decompress(cinfo: &mut jpeg_decompress_struct) -> boolean;
+    pub fn jpeg_read_raw_data(cinfo: &mut jpeg_decompress_struct, data: J12SAMPIMAGE_MUT, max_lines: JDIMENSION) -> JDIMENSION;
+    #[cfg(feature = "icc_io")]
+    pub fn jpeg_read_icc_profile(cinfo: &mut jpeg_decompress_struct, icc_data_ptr: *mut *mut u8, icc_data_len: *mut c_uint) -> boolean;
+    #[cfg(feature = "icc_io")]
+    pub fn jpeg_write_icc_profile(cinfo: &mut jpeg_compress_struct, icc_data_ptr: *const u8, icc_data_len: c_uint);
+    pub fn jpeg_skip_scanlines(cinfo: &mut jpeg_decompress_struct, num_lines: JDIMENSION) -> JDIMENSION;
+    pub fn jpeg_crop_scanline(cinfo: &mut jpeg_decompress_struct, xoffset: &mut JDIMENSION, width: &mut JDIMENSION);
+    pub fn jpeg_has_multiple_scans(cinfo: &jpeg_decompress_struct) -> boolean;
+    pub fn jpeg_start_output(cinfo: &mut jpeg_decompress_struct, scan_number: c_int) -> boolean;
+    pub fn jpeg_finish_output(cinfo: &mut jpeg_decompress_struct) -> boolean;
+    pub fn jpeg_input_complete(cinfo: &jpeg_decompress_struct) -> boolean;
+    pub fn jpeg_consume_input(cinfo: &mut jpeg_decompress_struct) -> c_int;
+    pub fn jpeg_calc_output_dimensions(cinfo: &mut jpeg_decompress_struct);
+    pub fn jpeg_save_markers(cinfo: &mut jpeg_decompress_struct, marker_code: c_int, length_limit: c_uint);
+    pub fn jpeg_set_marker_processor(cinfo: &mut jpeg_decompress_struct, marker_code: c_int, routine: jpeg_marker_parser_method);
+    pub fn jpeg_read_coefficients(cinfo: &mut jpeg_decompress_struct) -> *mut *mut jvirt_barray_control;
+    pub fn jpeg_write_coefficients(cinfo: &mut jpeg_compress_struct, coef_arrays: *mut *mut jvirt_barray_control);
+    pub fn jpeg_copy_critical_parameters(srcinfo: &jpeg_decompress_struct, dstinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_abort_compress(cinfo: &mut jpeg_compress_struct);
+    pub fn jpeg_abort_decompress(cinfo: &mut jpeg_decompress_struct);
+    pub fn jpeg_c_set_bool_param(cinfo: &mut jpeg_compress_struct, param: J_BOOLEAN_PARAM, value: boolean);
+    pub fn jpeg_c_get_bool_param(cinfo: &jpeg_compress_struct, param: J_BOOLEAN_PARAM) -> boolean;
+    pub fn jpeg_c_set_float_param(cinfo: &mut jpeg_compress_struct, param: J_FLOAT_PARAM, value: f32);
+    pub fn jpeg_c_get_float_param(cinfo: &jpeg_compress_struct, param: J_FLOAT_PARAM) -> f32;
+    pub fn jpeg_c_set_int_param(cinfo: &mut jpeg_compress_struct, param: J_INT_PARAM, value: c_int);
+    pub fn jpeg_c_get_int_param(cinfo: &jpeg_compress_struct, param: J_INT_PARAM) -> c_int;
+}
@@ -0,0 +1,115 @@
+//! Safe wrapper for `jpeg_progress_mgr`
+use crate::*;
+use std::ops::ControlFlow;
+use std::panic;
+
+/// State passed to the callback of `set_progress()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Overall progress estimate, from 0 to 1. It never goes backwards.
+    pub fraction: f32,
+    /// Passes done so far
+    pub completed_passes: c_int,
+    /// Expected number of passes. It may grow, e.g. when the decoder finds more scans in a progressive file.
+    pub total_passes: c_int,
+}
+
+/// Returning `Err` aborts the operation with that error
+type ProgressCallback = dyn FnMut(&mut jpeg_common_struct, Progress) -> Result<(), Error>;
+
+/// Progress monitor installed by `set_progress()` or `set_decode_limits()`. Keep it alive as long as the `cinfo` is used.
+#[repr(C)]
+pub struct ProgressMgr {
+    iface: jpeg_progress_mgr,
+    callback: Box<ProgressCallback>,
+    fraction: f32,
+    /// Monitor that was installed before this one, called first
+    previous: *mut jpeg_progress_mgr,
+}
+
+/// Points `cinfo.progress` back at the outer monitor, even if the inner one unwinds
+struct RestoreProgress<'a> {
+    cinfo: &'a mut jpeg_common_struct,
+    progress: *mut jpeg_progress_mgr,
+}
+
+impl Drop for RestoreProgress<'_> {
+    fn drop(&mut self) {
+        self.cinfo.progress = self.progress;
+    }
+}
+
+impl ProgressMgr {
+    unsafe extern "C-unwind" fn progress_monitor(cinfo: &mut jpeg_common_struct) {
+        let mgr = &mut *cinfo.progress.cast::<Self>();
+        if let Some(previous) = mgr.previous.as_mut() {
+            // libjpeg only updates the counters of the monitor it knows about
+            previous.pass_counter = mgr.iface.pass_counter;
+            previous.pass_limit = mgr.iface.pass_limit;
+            previous.completed_passes = mgr.iface.completed_passes;
+            previous.total_passes = mgr.iface.total_passes;
+            if let Some(progress_monitor) = previous.progress_monitor {
+                let restore = RestoreProgress { progress: cinfo.progress, cinfo: &mut *cinfo };
+                restore.cinfo.progress = previous;
+                progress_monitor(restore.cinfo);
+            }
+        }
+        let iface = &mgr.iface;
+        let total_passes = iface.total_passes.max(1);
+        let in_pass = if iface.pass_limit > 0 {
+            (iface.pass_counter as f32 / iface.pass_limit as f32).min(1.)
+        } else {
+            0.
+        };
+        let fraction = ((iface.completed_passes as f32 + in_pass) / total_passes as f32).min(1.);
+        mgr.fraction = mgr.fraction.max(fraction);
+        let progress = Progress {
+            fraction: mgr.fraction,
+            completed_passes: iface.completed_passes,
+            total_passes: iface.total_passes,
+        };
+        if let Err(err) = (mgr.callback)(cinfo, progress) {
+            // libjpeg is in the middle of a pass, so the caller cleans up after the unwind, like after `error_exit`
+            panic::resume_unwind(Box::new(err));
+        }
+    }
+
+    pub(crate) unsafe fn install(cinfo: &mut jpeg_common_struct, callback: Box<ProgressCallback>) -> Box<Self> {
+        let mut mgr = Box::new(Self {
+            iface: jpeg_progress_mgr {
+                progress_monitor: Some(Self::progress_monitor),
+                pass_counter: 0,
+                pass_limit: 0,
+                completed_passes: 0,
+                total_passes: 0,
+            },
+            callback,
+            fraction: 0.,
+            previous: cinfo.progress,
+        });
+        cinfo.progress = &mut mgr.iface;
+        mgr
+    }
+}
+
+/// Calls `callback` periodically during compression or decompression with `cinfo`.
+///
+/// Returning `ControlFlow::Break` unwinds to the nearest `catch()` with `Error::Cancelled`.
+/// Then call `jpeg_abort_compress`/`jpeg_abort_decompress` before reusing the `cinfo`, or destroy it.
+///
+/// If another monitor has already been installed (e.g. by `set_decode_limits()`), it's called before `callback`.
+///
+/// mozjpeg's trellis quantization and scan optimization make compression run many passes,
+/// and the number of passes isn't always known in advance, so the fraction is only an estimate.
+///
+/// # Safety
+///
+/// `cinfo` must be initialized, and the returned `ProgressMgr` must outlive its use.
+/// The previously installed monitor, if any, must outlive it too.
+#[must_use]
+pub unsafe fn set_progress(cinfo: &mut jpeg_common_struct, mut callback: impl FnMut(Progress) -> ControlFlow<()> + 'static) -> Box<ProgressMgr> {
+    ProgressMgr::install(cinfo, Box::new(move |_, progress| match callback(progress) {
+        ControlFlow::Continue(()) => Ok(()),
+        ControlFlow::Break(()) => Err(Error::Cancelled),
+    }))
+}
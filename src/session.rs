@@ -0,0 +1,136 @@
+//! RAII wrappers used by the safe helpers. They must be used inside `catch()`.
+// `validate` and `lossless` each use only part of this
+#![cfg_attr(not(all(feature = "validate", feature = "lossless")), allow(dead_code))]
+use crate::error::{ErrorMgr, Warning};
+use crate::*;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
+/// Decompressor reading from a slice
+pub(crate) struct Decompress<'src> {
+    cinfo: Box<jpeg_decompress_struct>,
+    err: Box<ErrorMgr>,
+    _src: PhantomData<&'src [u8]>,
+}
+
+impl<'src> Decompress<'src> {
+    pub fn new(data: &'src [u8]) -> Self {
+        unsafe {
+            let mut err = ErrorMgr::new();
+            err.input_len = Some(data.len());
+            let mut cinfo: Box<jpeg_decompress_struct> = Box::new(mem::zeroed());
+            cinfo.common.err = err.as_iface();
+            jpeg_create_decompress(&mut *cinfo);
+            jpeg_mem_src(&mut cinfo, data.as_ptr(), data.len() as c_ulong);
+            Self { cinfo, err, _src: PhantomData }
+        }
+    }
+
+    /// Warnings collected so far
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.err.take_warnings()
+    }
+}
+
+impl Deref for Decompress<'_> {
+    type Target = jpeg_decompress_struct;
+    fn deref(&self) -> &Self::Target { &self.cinfo }
+}
+
+impl DerefMut for Decompress<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.cinfo }
+}
+
+impl Drop for Decompress<'_> {
+    fn drop(&mut self) {
+        unsafe { jpeg_destroy_decompress(&mut self.cinfo) }
+    }
+}
+
+/// `jpeg_destination_mgr` that appends to a `Vec`
+#[repr(C)]
+struct VecDest {
+    iface: jpeg_destination_mgr,
+    buf: Vec<u8>,
+}
+
+impl VecDest {
+    unsafe fn from_cinfo(cinfo: &mut jpeg_compress_struct) -> &mut Self {
+        &mut *cinfo.dest.cast::<Self>()
+    }
+
+    unsafe extern "C-unwind" fn init_destination(cinfo: &mut jpeg_compress_struct) {
+        let dest = Self::from_cinfo(cinfo);
+        dest.buf.clear();
+        dest.buf.resize(4096, 0);
+        dest.iface.next_output_byte = dest.buf.as_mut_ptr();
+        dest.iface.free_in_buffer = dest.buf.len();
+    }
+
+    unsafe extern "C-unwind" fn empty_output_buffer(cinfo: &mut jpeg_compress_struct) -> boolean {
+        let dest = Self::from_cinfo(cinfo);
+        let used = dest.buf.len();
+        dest.buf.resize(used * 2, 0);
+        dest.iface.next_output_byte = dest.buf.as_mut_ptr().add(used);
+        dest.iface.free_in_buffer = dest.buf.len() - used;
+        1
+    }
+
+    unsafe extern "C-unwind" fn term_destination(cinfo: &mut jpeg_compress_struct) {
+        let dest = Self::from_cinfo(cinfo);
+        let used = dest.buf.len() - dest.iface.free_in_buffer;
+        dest.buf.truncate(used);
+    }
+}
+
+/// Compressor writing to a `Vec`
+pub(crate) struct Compress {
+    cinfo: Box<jpeg_compress_struct>,
+    _err: Box<ErrorMgr>,
+    dest: Box<VecDest>,
+}
+
+impl Compress {
+    pub fn new() -> Self {
+        unsafe {
+            let mut err = ErrorMgr::new();
+            let mut cinfo: Box<jpeg_compress_struct> = Box::new(mem::zeroed());
+            cinfo.common.err = err.as_iface();
+            jpeg_create_compress(&mut *cinfo);
+            let mut dest = Box::new(VecDest {
+                iface: jpeg_destination_mgr {
+                    next_output_byte: ptr::null_mut(),
+                    free_in_buffer: 0,
+                    init_destination: Some(VecDest::init_destination),
+                    empty_output_buffer: Some(VecDest::empty_output_buffer),
+                    term_destination: Some(VecDest::term_destination),
+                },
+                buf: Vec::new(),
+            });
+            cinfo.dest = &mut dest.iface;
+            Self { cinfo, _err: err, dest }
+        }
+    }
+
+    /// Calls `jpeg_finish_compress()` and returns the JPEG file
+    pub fn finish(mut self) -> Vec<u8> {
+        unsafe { jpeg_finish_compress(&mut self.cinfo) };
+        mem::take(&mut self.dest.buf)
+    }
+}
+
+impl Deref for Compress {
+    type Target = jpeg_compress_struct;
+    fn deref(&self) -> &Self::Target { &self.cinfo }
+}
+
+impl DerefMut for Compress {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.cinfo }
+}
+
+impl Drop for Compress {
+    fn drop(&mut self) {
+        unsafe { jpeg_destroy_compress(&mut self.cinfo) }
+    }
+}
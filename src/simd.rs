@@ -0,0 +1,260 @@
+//! Runtime reporting of SIMD kernels, via the `jsimd_can_*` functions, and forcing of the instruction set
+use crate::*;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_char;
+use std::sync::OnceLock;
+
+/// Instruction set picked by libjpeg-turbo's CPU detection
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InstructionSet {
+    /// The portable C code is used. SIMD wasn't compiled in (e.g. `nasm` was missing), or the CPU doesn't support it.
+    None,
+    Mmx,
+    Sse2,
+    Avx2,
+    Neon,
+    /// MIPS DSPr2
+    Dspr2,
+    /// PowerPC AltiVec
+    Altivec,
+    /// WebAssembly SIMD128 (the `wasm_simd` feature)
+    Simd128,
+    /// Auto-vectorized Rust (the `portable_simd` feature)
+    Portable,
+}
+
+/// Whether a SIMD version of one kernel is used
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SimdKernel {
+    /// Name of the `jsimd_can_*` function without the prefix, e.g. `rgb_ycc` or `fdct_islow`
+    pub name: &'static str,
+    pub active: bool,
+}
+
+/// Returned by `simd_info()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimdInfo {
+    /// `InstructionSet::None` if no kernel is active
+    pub instruction_set: InstructionSet,
+    /// All kernels that have SIMD versions in some instruction set
+    pub kernels: Vec<SimdKernel>,
+}
+
+impl InstructionSet {
+    /// Suffix of `jsimd.c`'s `JSIMD_FORCE*` environment variables
+    fn env_name(self) -> &'static str {
+        match self {
+            Self::None => "NONE",
+            Self::Mmx => "MMX",
+            Self::Sse2 => "SSE2",
+            Self::Avx2 => "AVX2",
+            Self::Neon => "NEON",
+            Self::Dspr2 => "DSPR2",
+            Self::Altivec => "ALTIVEC",
+            Self::Simd128 => "SIMD128",
+            Self::Portable => "PORTABLE",
+        }
+    }
+
+    /// Whether `jsimd.c` for the target can be forced to use it
+    fn can_force(self) -> bool {
+        match self {
+            Self::None => true,
+            Self::Mmx => cfg!(target_arch = "x86"),
+            Self::Sse2 | Self::Avx2 => cfg!(any(target_arch = "x86_64", target_arch = "x86")) && !cfg!(mozjpeg_portable_simd),
+            Self::Neon => cfg!(any(target_arch = "arm", target_arch = "aarch64")),
+            Self::Dspr2 => cfg!(target_arch = "mips"),
+            Self::Altivec => cfg!(any(target_arch = "powerpc", target_arch = "powerpc64")),
+            Self::Simd128 => cfg!(all(feature = "wasm_simd", target_arch = "wasm32")),
+            Self::Portable => cfg!(mozjpeg_portable_simd),
+        }
+    }
+}
+
+impl SimdInfo {
+    /// Whether the SIMD version of the kernel (e.g. `"fdct_islow"`) is used
+    #[must_use]
+    pub fn is_active(&self, name: &str) -> bool {
+        self.kernels.iter().any(|k| k.active && k.name == name)
+    }
+}
+
+macro_rules! kernels {
+    ($($name:ident),* $(,)?) => {
+        /// Also used by the tests in `lib.rs`
+        pub(crate) mod can {
+            use crate::c_int;
+            extern_c! {
+                extern "C" {
+                    $(pub fn $name() -> c_int;)*
+                }
+            }
+        }
+
+        const KERNELS: &[(&str, unsafe extern "C" fn() -> c_int)] = &[
+            $((stringify!($name), can::$name),)*
+        ];
+    };
+}
+
+kernels! {
+    jsimd_can_rgb_ycc, jsimd_can_rgb_gray, jsimd_can_ycc_rgb, jsimd_can_ycc_rgb565, jsimd_c_can_null_convert,
+    jsimd_can_h2v2_downsample, jsimd_can_h2v1_downsample, jsimd_can_h2v2_smooth_downsample,
+    jsimd_can_h2v2_upsample, jsimd_can_h2v1_upsample, jsimd_can_int_upsample,
+    jsimd_can_h2v2_fancy_upsample, jsimd_can_h2v1_fancy_upsample, jsimd_can_h1v2_fancy_upsample,
+    jsimd_can_h2v2_merged_upsample, jsimd_can_h2v1_merged_upsample,
+    jsimd_can_convsamp, jsimd_can_convsamp_float, jsimd_can_fdct_islow, jsimd_can_fdct_ifast, jsimd_can_fdct_float,
+    jsimd_can_quantize, jsimd_can_quantize_float,
+    jsimd_can_idct_2x2, jsimd_can_idct_4x4, jsimd_can_idct_6x6, jsimd_can_idct_12x12,
+    jsimd_can_idct_islow, jsimd_can_idct_ifast, jsimd_can_idct_float,
+    jsimd_can_huff_encode_one_block, jsimd_can_encode_mcu_AC_first_prepare, jsimd_can_encode_mcu_AC_refine_prepare,
+}
+
+/// Reports which SIMD kernels are used at runtime, and in which instruction set.
+///
+/// Whether SIMD is used depends on the build (`with_simd`/`nasm_simd` features and whether
+/// `nasm` or `gas` was found) and on the CPU, so this is the way to catch silent fallbacks to C.
+/// It reflects `force_instruction_set()`, and prevents it from being called afterwards.
+#[must_use]
+pub fn simd_info() -> SimdInfo {
+    let kernels: Vec<_> = KERNELS.iter().map(|&(name, can)| SimdKernel {
+        name: name.trim_start_matches("jsimd_can_").trim_start_matches("jsimd_c_can_"),
+        active: unsafe { can() } != 0,
+    }).collect();
+    let instruction_set = if kernels.iter().any(|k| k.active) { instruction_set() } else { InstructionSet::None };
+    SimdInfo { instruction_set, kernels }
+}
+
+/// Returned by `force_instruction_set()`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ForceSimdError {
+    /// The instruction set doesn't exist on this architecture
+    Unsupported(InstructionSet),
+    /// libjpeg has already picked an instruction set (or a different one has been forced)
+    AlreadyInitialized,
+}
+
+impl fmt::Display for ForceSimdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(set) => write!(f, "{set:?} is not available on this architecture"),
+            Self::AlreadyInitialized => f.write_str("SIMD has already been initialized"),
+        }
+    }
+}
+
+impl std::error::Error for ForceSimdError {}
+
+/// `Some` if forced by `force_instruction_set()`.
+/// Set on first use, because libjpeg detects the CPU once per thread, and all threads must agree.
+static FORCED: OnceLock<Option<InstructionSet>> = OnceLock::new();
+
+fn forced_instruction_set() -> Option<InstructionSet> {
+    *FORCED.get_or_init(|| None)
+}
+
+/// Makes libjpeg use only the given instruction set, or with `InstructionSet::None`, only the plain C code.
+///
+/// This is process-wide, and must be called before any image is compressed or decompressed
+/// (or `simd_info()` is called). Otherwise it fails with `ForceSimdError::AlreadyInitialized`.
+///
+/// It's useful for bit-exact output across different CPUs (e.g. SSE2 and AVX2 versions of `JDCT_IFAST` can differ),
+/// and for benchmarking. If the CPU doesn't support the forced instruction set, SIMD is disabled.
+/// This is equivalent of libjpeg-turbo's `JSIMD_FORCE*` environment variables, which this crate doesn't read.
+pub fn force_instruction_set(set: InstructionSet) -> Result<(), ForceSimdError> {
+    if !set.can_force() {
+        return Err(ForceSimdError::Unsupported(set));
+    }
+    if *FORCED.get_or_init(|| Some(set)) != Some(set) {
+        return Err(ForceSimdError::AlreadyInitialized);
+    }
+    Ok(())
+}
+
+/// Used instead of `getenv_s` by `jsimd.c` (see `build.rs`), so that it sees only the `JSIMD_FORCE*` variable for `force_instruction_set()`
+#[no_mangle]
+unsafe extern "C" fn mozjpeg_sys_simd_getenv_s(buffer: *mut c_char, buffer_size: usize, name: *const c_char) -> c_int {
+    if buffer.is_null() || buffer_size < 2 || name.is_null() {
+        return 1;
+    }
+    let is_set = forced_instruction_set().is_some_and(|set| {
+        CStr::from_ptr(name).to_bytes().strip_prefix(b"JSIMD_FORCE") == Some(set.env_name().as_bytes())
+    });
+    *buffer = if is_set { b'1' as c_char } else { 0 };
+    *buffer.add(1) = 0;
+    0
+}
+
+/// Which version of the Rust kernels (`jsimd_rust.rs`) to use, which depends on
+/// `force_instruction_set()` instead of `jsimd.c`. `None` if they're disabled.
+#[cfg(mozjpeg_rust_simd)]
+pub(crate) fn rust_instruction_set() -> InstructionSet {
+    #[cfg(mozjpeg_x86_intrinsics_simd)]
+    {
+        let avx2 = std::arch::is_x86_feature_detected!("avx2");
+        match forced_instruction_set() {
+            None if avx2 => InstructionSet::Avx2,
+            None | Some(InstructionSet::Sse2) => InstructionSet::Sse2,
+            Some(InstructionSet::Avx2) if avx2 => InstructionSet::Avx2,
+            Some(_) => InstructionSet::None,
+        }
+    }
+    #[cfg(not(mozjpeg_x86_intrinsics_simd))]
+    {
+        let set = if cfg!(mozjpeg_portable_simd) { InstructionSet::Portable } else { InstructionSet::Simd128 };
+        if forced_instruction_set() == Some(InstructionSet::None) { InstructionSet::None } else { set }
+    }
+}
+
+/// Only valid if some kernel is active
+#[cfg(mozjpeg_rust_simd)]
+fn instruction_set() -> InstructionSet {
+    rust_instruction_set()
+}
+
+/// Only valid if some kernel is active
+#[cfg(not(mozjpeg_rust_simd))]
+fn instruction_set() -> InstructionSet {
+    #[cfg(all(mozjpeg_simd, any(target_arch = "x86_64", target_arch = "x86")))]
+    {
+        // jsimd.h
+        const JSIMD_MMX: c_uint = 0x01;
+        const JSIMD_SSE2: c_uint = 0x08;
+        const JSIMD_AVX2: c_uint = 0x80;
+        extern_c! {
+            extern "C" {
+                fn jpeg_simd_cpu_support() -> c_uint;
+            }
+        }
+        // same as `init_simd()` in `jsimd.c`
+        let mut support = unsafe { jpeg_simd_cpu_support() };
+        match forced_instruction_set() {
+            Some(InstructionSet::Avx2) => support &= JSIMD_AVX2,
+            Some(InstructionSet::Sse2) => support &= JSIMD_SSE2,
+            Some(InstructionSet::Mmx) => support &= JSIMD_MMX,
+            _ => {},
+        }
+        if support & JSIMD_AVX2 != 0 {
+            return InstructionSet::Avx2;
+        }
+        if support & JSIMD_SSE2 != 0 {
+            return InstructionSet::Sse2;
+        }
+        if support & JSIMD_MMX != 0 {
+            return InstructionSet::Mmx;
+        }
+    }
+    if cfg!(any(target_arch = "arm", target_arch = "aarch64")) {
+        InstructionSet::Neon
+    } else if cfg!(target_arch = "mips") {
+        InstructionSet::Dspr2
+    } else if cfg!(any(target_arch = "powerpc", target_arch = "powerpc64")) {
+        InstructionSet::Altivec
+    } else if cfg!(target_arch = "wasm32") {
+        InstructionSet::Simd128
+    } else {
+        InstructionSet::None
+    }
+}
@@ -11,11 +11,16 @@ pub const JXFORM_CODE_JXFORM_TRANSVERSE: JXFORM_CODE = 4;
 pub const JXFORM_CODE_JXFORM_ROT_90: JXFORM_CODE = 5;
 pub const JXFORM_CODE_JXFORM_ROT_180: JXFORM_CODE = 6;
 pub const JXFORM_CODE_JXFORM_ROT_270: JXFORM_CODE = 7;
+/// Fill the crop region with neutral gray
+pub const JXFORM_CODE_JXFORM_WIPE: JXFORM_CODE = 8;
+/// Insert another image (`drop_ptr`) at the crop region
+pub const JXFORM_CODE_JXFORM_DROP: JXFORM_CODE = 9;
 pub type JXFORM_CODE = ::std::os::raw::c_uint;
 pub const JCROP_CODE_JCROP_UNSET: JCROP_CODE = 0;
 pub const JCROP_CODE_JCROP_POS: JCROP_CODE = 1;
 pub const JCROP_CODE_JCROP_NEG: JCROP_CODE = 2;
 pub const JCROP_CODE_JCROP_FORCE: JCROP_CODE = 3;
+pub const JCROP_CODE_JCROP_REFLECT: JCROP_CODE = 4;
 pub type JCROP_CODE = ::std::os::raw::c_uint;
 pub const JCOPY_OPTION_JCOPYOPT_NONE: JCOPY_OPTION = 0;
 pub const JCOPY_OPTION_JCOPYOPT_COMMENTS: JCOPY_OPTION = 1;
@@ -41,12 +46,19 @@ pub struct jpeg_transform_info {
     pub crop_xoffset_set: JCROP_CODE,
     pub crop_yoffset: JDIMENSION,
     pub crop_yoffset_set: JCROP_CODE,
+    /// Image to insert for `JXFORM_DROP`. Its header must have been read.
+    pub drop_ptr: j_decompress_ptr,
+    /// Coefficients of `drop_ptr` from `jpeg_read_coefficients()`
+    pub drop_coef_arrays: *mut jvirt_barray_ptr,
     pub num_components: ::std::os::raw::c_int,
     pub workspace_coef_arrays: *mut jvirt_barray_ptr,
     pub output_width: JDIMENSION,
     pub output_height: JDIMENSION,
     pub x_crop_offset: JDIMENSION,
     pub y_crop_offset: JDIMENSION,
+    /// drop/wipe dimensions measured in iMCUs
+    pub drop_width: JDIMENSION,
+    pub drop_height: JDIMENSION,
     pub iMCU_sample_width: ::std::os::raw::c_int,
     pub iMCU_sample_height: ::std::os::raw::c_int,
 }
@@ -60,35 +72,51 @@ pub unsafe fn jtransform_execute_transformation(
     jtransform_execute_transform(srcinfo, dstinfo, src_coef_arrays, info)
 }
 
-extern "C-unwind" {
-    pub fn jtransform_adjust_parameters(
-        srcinfo: j_decompress_ptr,
-        dstinfo: j_compress_ptr,
-        src_coef_arrays: *mut jvirt_barray_ptr,
-        info: *mut jpeg_transform_info,
-    ) -> *mut jvirt_barray_ptr;
+extern_c! {
+    extern "C-unwind" {
+        /// Parses `WxH+X+Y` crop spec (as used by `jpegtran -crop`) into `info`
+        pub fn jtransform_parse_crop_spec(
+            info: *mut jpeg_transform_info,
+            spec: *const ::std::os::raw::c_char,
+        ) -> boolean;
 
-    pub fn jtransform_execute_transform(
-        srcinfo: j_decompress_ptr,
-        dstinfo: j_compress_ptr,
-        src_coef_arrays: *mut jvirt_barray_ptr,
-        info: *mut jpeg_transform_info,
-    );
+        pub fn jtransform_adjust_parameters(
+            srcinfo: j_decompress_ptr,
+            dstinfo: j_compress_ptr,
+            src_coef_arrays: *mut jvirt_barray_ptr,
+            info: *mut jpeg_transform_info,
+        ) -> *mut jvirt_barray_ptr;
 
-    pub fn jtransform_request_workspace(
-        srcinfo: j_decompress_ptr,
-        info: *mut jpeg_transform_info,
-    ) -> boolean;
+        pub fn jtransform_execute_transform(
+            srcinfo: j_decompress_ptr,
+            dstinfo: j_compress_ptr,
+            src_coef_arrays: *mut jvirt_barray_ptr,
+            info: *mut jpeg_transform_info,
+        );
 
-    pub fn jcopy_markers_setup(
-        srcinfo: j_decompress_ptr,
-        option: JCOPY_OPTION,
-    );
+        pub fn jtransform_request_workspace(
+            srcinfo: j_decompress_ptr,
+            info: *mut jpeg_transform_info,
+        ) -> boolean;
 
-    pub fn jcopy_markers_execute(
-        srcinfo: j_decompress_ptr,
-        dstinfo: j_compress_ptr,
-        option: JCOPY_OPTION,
-    );
+        pub fn jtransform_perfect_transform(
+            image_width: JDIMENSION,
+            image_height: JDIMENSION,
+            MCU_width: c_int,
+            MCU_height: c_int,
+            transform: JXFORM_CODE,
+        ) -> boolean;
+
+        pub fn jcopy_markers_setup(
+            srcinfo: j_decompress_ptr,
+            option: JCOPY_OPTION,
+        );
+
+        pub fn jcopy_markers_execute(
+            srcinfo: j_decompress_ptr,
+            dstinfo: j_compress_ptr,
+            option: JCOPY_OPTION,
+        );
+    }
 }
 
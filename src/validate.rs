@@ -0,0 +1,268 @@
+//! Checking JPEG files for corruption
+use crate::error::catch;
+use crate::session::Decompress;
+use crate::*;
+#[cfg(feature = "icc_io")]
+use std::ptr;
+
+/// Result of `validate()`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Report {
+    /// Problems with the marker structure of the file
+    pub issues: Vec<MarkerIssue>,
+    /// Warnings reported by libjpeg while decoding the whole image
+    pub warnings: Vec<Warning>,
+    /// Fatal error that stopped decoding
+    pub error: Option<Error>,
+}
+
+impl Report {
+    /// No errors, warnings nor structural issues found
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty() && self.warnings.is_empty() && self.error.is_none()
+    }
+
+    /// libjpeg has reported a warning with this code
+    #[must_use]
+    pub fn has_warning(&self, code: J_MESSAGE_CODE) -> bool {
+        self.warnings.iter().any(|w| w.code == code)
+    }
+}
+
+/// Structural problem found at `offset` bytes into the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkerIssue {
+    pub offset: usize,
+    pub problem: MarkerProblem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MarkerProblem {
+    /// File doesn't start with the SOI marker
+    MissingSoi,
+    /// File ends without the EOI marker (it's probably truncated)
+    MissingEoi,
+    /// Marker in a place where it's not allowed, e.g. a second SOI, or SOS before SOF
+    MarkerOutOfOrder(u8),
+    /// More than one SOF (frame header) marker
+    DuplicateSof,
+    /// Declared length of the segment is invalid for this kind of marker
+    BadSegmentLength { marker: u8, length: u16 },
+    /// Segment extends beyond the end of the file
+    TruncatedSegment { marker: u8 },
+    /// Bytes between segments that aren't part of any marker
+    ExtraneousBytes(usize),
+    /// Restart markers in entropy-coded data aren't in sequence
+    RestartOutOfSequence { expected: u8, found: u8 },
+    /// Data after the EOI marker
+    TrailingData(usize),
+}
+
+/// Decodes the whole image and checks the file structure, reporting all problems found,
+/// rather than stopping at the first one or silently accepting a partial image.
+///
+/// Corrupted and truncated files usually decode "successfully" in libjpeg,
+/// with gray or garbled areas and only a `JWRN_*` warning (such as `JWRN_JPEG_EOF`,
+/// `JWRN_EXTRANEOUS_DATA`, `JWRN_HUFF_BAD_CODE` or `JWRN_BOGUS_PROGRESSION`).
+#[must_use]
+pub fn validate(jpeg: &[u8]) -> Report {
+    let mut report = Report {
+        issues: check_markers(jpeg),
+        ..Report::default()
+    };
+
+    let mut warnings = Vec::new();
+    if let Err(err) = catch(|| {
+        let mut cinfo = Decompress::new(jpeg);
+        // warnings are needed even if decoding fails
+        let res = catch(|| unsafe { decode_all(&mut cinfo) });
+        warnings = cinfo.take_warnings();
+        res
+    }) {
+        report.error = Some(err);
+    }
+    report.warnings = warnings;
+    report
+}
+
+#[cfg(feature = "icc_io")]
+extern "C" {
+    fn free(ptr: *mut c_void);
+}
+
+unsafe fn decode_all(cinfo: &mut Decompress<'_>) -> Result<(), Error> {
+    #[cfg(feature = "icc_io")]
+    jpeg_save_markers(cinfo, jpeg_marker::APP0 as c_int + 2, 0xFFFF);
+    jpeg_read_header(cinfo, true as boolean);
+    #[cfg(feature = "icc_io")]
+    {
+        let mut icc = ptr::null_mut();
+        let mut icc_len = 0;
+        if jpeg_read_icc_profile(cinfo, &mut icc, &mut icc_len) != 0 {
+            free(icc.cast());
+        }
+    }
+    jpeg_start_decompress(cinfo);
+    let row_len = cinfo.output_width as usize * cinfo.output_components as usize;
+    let mut row = vec![0u8; row_len];
+    while cinfo.output_scanline < cinfo.output_height {
+        jpeg_read_scanlines(cinfo, [row.as_mut_ptr()].as_mut_ptr(), 1);
+    }
+    jpeg_finish_decompress(cinfo);
+    Ok(())
+}
+
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DQT: u8 = 0xDB;
+const DRI: u8 = 0xDD;
+const DHT: u8 = 0xC4;
+const TEM: u8 = 0x01;
+
+fn is_sof(marker: u8) -> bool {
+    matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC)
+}
+
+fn is_rst(marker: u8) -> bool {
+    matches!(marker, 0xD0..=0xD7)
+}
+
+/// Walks the markers and segments without decoding
+fn check_markers(data: &[u8]) -> Vec<MarkerIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |offset, problem| issues.push(MarkerIssue { offset, problem });
+
+    if !data.starts_with(&[0xFF, SOI]) {
+        issue(0, MarkerProblem::MissingSoi);
+        return issues;
+    }
+
+    let mut seen_sof = false;
+    let mut seen_sos = false;
+    let mut pos = 2;
+    loop {
+        // Extraneous bytes and fill bytes before the marker
+        let start = pos;
+        while pos < data.len() && data[pos] != 0xFF {
+            pos += 1;
+        }
+        if pos > start {
+            issue(start, MarkerProblem::ExtraneousBytes(pos - start));
+        }
+        while pos + 1 < data.len() && data[pos + 1] == 0xFF {
+            pos += 1;
+        }
+        if pos + 1 >= data.len() {
+            issue(data.len(), MarkerProblem::MissingEoi);
+            break;
+        }
+
+        let marker_pos = pos;
+        let marker = data[pos + 1];
+        pos += 2;
+        match marker {
+            EOI => {
+                if !seen_sos {
+                    issue(marker_pos, MarkerProblem::MarkerOutOfOrder(marker));
+                }
+                if pos < data.len() {
+                    issue(pos, MarkerProblem::TrailingData(data.len() - pos));
+                }
+                break;
+            },
+            SOI | 0x00 => {
+                issue(marker_pos, MarkerProblem::MarkerOutOfOrder(marker));
+                continue;
+            },
+            m if is_rst(m) => {
+                issue(marker_pos, MarkerProblem::MarkerOutOfOrder(marker));
+                continue;
+            },
+            TEM => continue,
+            _ => {},
+        }
+
+        let Some(length) = data.get(pos..pos + 2).map(|l| u16::from_be_bytes([l[0], l[1]])) else {
+            issue(marker_pos, MarkerProblem::TruncatedSegment { marker });
+            break;
+        };
+        let Some(segment) = data.get(pos + 2..pos + length as usize).filter(|_| length >= 2) else {
+            issue(marker_pos, if length < 2 { MarkerProblem::BadSegmentLength { marker, length } } else { MarkerProblem::TruncatedSegment { marker } });
+            break;
+        };
+        pos += length as usize;
+
+        if !segment_length_ok(marker, segment) {
+            issue(marker_pos, MarkerProblem::BadSegmentLength { marker, length });
+        }
+        if is_sof(marker) {
+            if seen_sof {
+                issue(marker_pos, MarkerProblem::DuplicateSof);
+            }
+            seen_sof = true;
+        } else if marker == SOS {
+            if !seen_sof {
+                issue(marker_pos, MarkerProblem::MarkerOutOfOrder(marker));
+            }
+            seen_sos = true;
+            pos = skip_entropy_coded_data(data, pos, &mut issue);
+        }
+    }
+    issues
+}
+
+/// Checks that the segment length matches its content
+fn segment_length_ok(marker: u8, segment: &[u8]) -> bool {
+    match marker {
+        m if is_sof(m) => segment.len() >= 6 && segment.len() == 6 + 3 * segment[5] as usize,
+        SOS => !segment.is_empty() && segment.len() == 4 + 2 * segment[0] as usize,
+        DRI => segment.len() == 2,
+        DQT => {
+            let mut rest = segment;
+            while let Some(&pq_tq) = rest.first() {
+                let table_len = 1 + 64 * (1 + (pq_tq >> 4) as usize);
+                let Some(tail) = rest.get(table_len..) else { return false };
+                rest = tail;
+            }
+            !segment.is_empty()
+        },
+        DHT => {
+            let mut rest = segment;
+            while !rest.is_empty() {
+                let Some(counts) = rest.get(1..17) else { return false };
+                let table_len = 17 + counts.iter().map(|&c| c as usize).sum::<usize>();
+                let Some(tail) = rest.get(table_len..) else { return false };
+                rest = tail;
+            }
+            !segment.is_empty()
+        },
+        _ => true,
+    }
+}
+
+/// Returns position of the first marker after the scan data
+fn skip_entropy_coded_data(data: &[u8], mut pos: usize, issue: &mut impl FnMut(usize, MarkerProblem)) -> usize {
+    let mut next_rst = 0;
+    while pos + 1 < data.len() {
+        if data[pos] != 0xFF {
+            pos += 1;
+            continue;
+        }
+        match data[pos + 1] {
+            0x00 | 0xFF => pos += 1,
+            m if is_rst(m) => {
+                let found = m - 0xD0;
+                if found != next_rst {
+                    issue(pos, MarkerProblem::RestartOutOfSequence { expected: next_rst, found });
+                }
+                next_rst = (found + 1) % 8;
+                pos += 2;
+            },
+            _ => return pos,
+        }
+    }
+    data.len()
+}
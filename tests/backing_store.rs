@@ -0,0 +1,27 @@
+#![cfg(feature = "backing_store")]
+
+use mozjpeg_sys::*;
+mod common;
+
+fn decode(data: &[u8], max_memory_to_use: c_long) -> Vec<u8> {
+    common::decode(data, |cinfo| unsafe { (*cinfo.common.mem).max_memory_to_use = max_memory_to_use }).0
+}
+
+#[test]
+fn spills_to_temp_files() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    // coefficients of the progressive image need over 1MB
+    assert!(decode(&data, 0) == decode(&data, 200_000));
+}
+
+#[test]
+#[cfg(feature = "safe_api")]
+fn memory_limit_with_backing_store() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    let mut err = ErrorMgr::new();
+    common::try_decode(&data, &mut err, |cinfo| {
+        unsafe { set_memory_limit(&mut cinfo.common, 300_000) };
+        Ok(())
+    }).unwrap();
+    assert!(err.warnings().is_empty());
+}
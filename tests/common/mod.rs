@@ -0,0 +1,57 @@
+//! Decoding with the raw API, shared by the tests.
+// Each test uses only some of these
+#![allow(dead_code)]
+use mozjpeg_sys::*;
+use std::mem;
+
+/// Decodes all scanlines with libjpeg's default error handling, which exits the process on errors.
+/// `setup` can change `cinfo` after `jpeg_read_header()`.
+///
+/// Returns the pixels, width and height.
+pub fn decode(data: &[u8], setup: impl FnOnce(&mut jpeg_decompress_struct)) -> (Vec<u8>, JDIMENSION, JDIMENSION) {
+    unsafe {
+        let mut err: jpeg_error_mgr = mem::zeroed();
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = jpeg_std_error(&mut err);
+        jpeg_create_decompress(&mut cinfo);
+        jpeg_mem_src(&mut cinfo, data.as_ptr(), data.len() as _);
+        jpeg_read_header(&mut cinfo, true as boolean);
+        setup(&mut cinfo);
+        let res = read_pixels(&mut cinfo);
+        jpeg_destroy_decompress(&mut cinfo);
+        res
+    }
+}
+
+/// Like `decode()`, but with `err` installed, inside `catch()`.
+/// Whatever `setup` returns is kept until `cinfo` is destroyed.
+#[cfg(feature = "safe_api")]
+pub fn try_decode<T>(data: &[u8], err: &mut ErrorMgr, setup: impl FnOnce(&mut jpeg_decompress_struct) -> Result<T, Error>) -> Result<(Vec<u8>, JDIMENSION, JDIMENSION), Error> {
+    unsafe {
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = err.as_iface();
+        jpeg_create_decompress(&mut cinfo);
+        let mut keep = None;
+        let res = catch(|| {
+            jpeg_mem_src(&mut cinfo, data.as_ptr(), data.len() as _);
+            jpeg_read_header(&mut cinfo, true as boolean);
+            keep = Some(setup(&mut cinfo)?);
+            Ok(read_pixels(&mut cinfo))
+        });
+        jpeg_destroy_decompress(&mut cinfo);
+        drop(keep);
+        res
+    }
+}
+
+unsafe fn read_pixels(cinfo: &mut jpeg_decompress_struct) -> (Vec<u8>, JDIMENSION, JDIMENSION) {
+    jpeg_start_decompress(cinfo);
+    let (width, height) = (cinfo.output_width, cinfo.output_height);
+    let row_len = width as usize * cinfo.output_components as usize;
+    let mut pixels = vec![0u8; row_len * height as usize];
+    for row in pixels.chunks_exact_mut(row_len) {
+        jpeg_read_scanlines(cinfo, [row.as_mut_ptr()].as_mut_ptr(), 1);
+    }
+    jpeg_finish_decompress(cinfo);
+    (pixels, width, height)
+}
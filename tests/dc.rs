@@ -1,3 +1,5 @@
+#![cfg(not(target_arch = "wasm32"))] // no files, and `cloudflare-soos` is built for wasm-bindgen
+
 use crate::MarkerData::Scan;
 use cloudflare_soos::jpeg::*;
 use mozjpeg_sys::*;
@@ -0,0 +1,24 @@
+//! A separate test binary, because the setting is process-wide and can't be changed after first use
+use mozjpeg_sys::*;
+
+#[test]
+fn force_no_simd() {
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(force_instruction_set(InstructionSet::Neon), Err(ForceSimdError::Unsupported(InstructionSet::Neon)));
+
+    force_instruction_set(InstructionSet::None).unwrap();
+    // The same setting can be repeated
+    force_instruction_set(InstructionSet::None).unwrap();
+
+    let info = simd_info();
+    assert_eq!(info.instruction_set, InstructionSet::None);
+    assert!(info.kernels.iter().all(|k| !k.active), "{info:?}");
+
+    // libjpeg has been initialized by `simd_info()`, and other threads must see the same setting
+    std::thread::spawn(|| {
+        assert!(simd_info().kernels.iter().all(|k| !k.active));
+    }).join().unwrap();
+
+    #[cfg(all(any(target_arch = "x86_64", target_arch = "x86"), not(feature = "portable_simd")))]
+    assert_eq!(force_instruction_set(InstructionSet::Sse2), Err(ForceSimdError::AlreadyInitialized));
+}
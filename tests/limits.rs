@@ -0,0 +1,53 @@
+#![cfg(feature = "safe_api")]
+
+use mozjpeg_sys::*;
+use std::cell::Cell;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+use std::time::Instant;
+
+mod common;
+
+fn decode(data: &[u8], limits: DecodeLimits) -> Result<(), Error> {
+    decode_with_progress(data, limits, |_| ControlFlow::Continue(()))
+}
+
+fn decode_with_progress(data: &[u8], limits: DecodeLimits, callback: impl FnMut(Progress) -> ControlFlow<()> + 'static) -> Result<(), Error> {
+    common::try_decode(data, &mut ErrorMgr::new(), |cinfo| unsafe {
+        limits.check_header(cinfo)?;
+        let progress = set_progress(&mut cinfo.common, callback);
+        Ok((progress, set_decode_limits(cinfo, limits)))
+    }).map(drop)
+}
+
+#[test]
+fn limits() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    decode(&data, DecodeLimits::default()).unwrap();
+    decode(&data, DecodeLimits { max_scans: Some(20), max_pixels: Some(800 * 723), deadline: None }).unwrap();
+
+    let res = decode(&data, DecodeLimits { max_scans: Some(3), ..Default::default() });
+    assert_eq!(Err(Error::LimitExceeded(Limit::Scans(4))), res);
+
+    let res = decode(&data, DecodeLimits { max_pixels: Some(100_000), ..Default::default() });
+    assert_eq!(Err(Error::LimitExceeded(Limit::Pixels(800 * 723))), res);
+
+    let res = decode(&data, DecodeLimits { deadline: Some(Instant::now()), ..Default::default() });
+    assert_eq!(Err(Error::LimitExceeded(Limit::Deadline)), res);
+}
+
+#[test]
+fn limits_with_progress() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    let calls = Rc::new(Cell::new(0));
+    let calls2 = Rc::clone(&calls);
+    let res = decode_with_progress(&data, DecodeLimits { max_scans: Some(3), ..Default::default() }, move |_| {
+        calls2.set(calls2.get() + 1);
+        ControlFlow::Continue(())
+    });
+    assert_eq!(Err(Error::LimitExceeded(Limit::Scans(4))), res);
+    assert!(calls.get() > 0);
+
+    let res = decode_with_progress(&data, DecodeLimits::default(), |p| if p.fraction > 0.1 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) });
+    assert_eq!(Err(Error::Cancelled), res);
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "log")]
+
+use log::kv::Key;
+use mozjpeg_sys::*;
+use std::mem;
+use std::sync::Mutex;
+
+struct Capture(Mutex<Vec<(log::Level, Option<i64>, String)>>);
+
+impl log::Log for Capture {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.target() == "mozjpeg"
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.enabled(record.metadata()) {
+            let code = record.key_values().get(Key::from("code")).and_then(|v| v.to_i64());
+            self.0.lock().unwrap().push((record.level(), code, record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+
+#[test]
+fn trace_and_errors_are_logged() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    let mut err = ErrorMgr::new();
+    err.set_trace_level(1);
+    unsafe {
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = err.as_iface();
+        jpeg_create_decompress(&mut cinfo);
+        catch(|| {
+            jpeg_mem_src(&mut cinfo, data.as_ptr(), data.len() as _);
+            jpeg_read_header(&mut cinfo, true as boolean);
+            Ok(())
+        }).unwrap();
+        jpeg_abort_decompress(&mut cinfo);
+        let res = catch(|| {
+            jpeg_mem_src(&mut cinfo, b"GIF89a".as_ptr(), 6);
+            jpeg_read_header(&mut cinfo, true as boolean);
+            Ok(())
+        });
+        assert!(res.is_err());
+        jpeg_destroy_decompress(&mut cinfo);
+    }
+
+    let logged = LOGGER.0.lock().unwrap();
+    let sof = logged.iter().find(|(_, code, _)| *code == Some(JTRC_SOF as i64)).unwrap();
+    assert_eq!(log::Level::Trace, sof.0);
+    assert!(sof.2.contains("width=800"), "{sof:?}");
+    assert!(logged.iter().any(|(level, code, _)| *level == log::Level::Trace && *code == Some(JTRC_DQT as i64)));
+    assert!(logged.iter().any(|(level, code, _)| *level == log::Level::Error && *code == Some(JERR_NO_SOI as i64)));
+}
@@ -0,0 +1,174 @@
+#![cfg(feature = "lossless")]
+
+use mozjpeg_sys::*;
+use std::mem;
+
+mod common;
+
+fn encode_gray(pixels: &[u8], width: u32, height: u32, quality: i32) -> Vec<u8> {
+    unsafe {
+        let mut err = mem::zeroed();
+        let mut cinfo: jpeg_compress_struct = mem::zeroed();
+        cinfo.common.err = jpeg_std_error(&mut err);
+        jpeg_create_compress(&mut cinfo);
+        let mut buf = std::ptr::null_mut();
+        let mut bufsize = 0;
+        jpeg_mem_dest(&mut cinfo, &mut buf, &mut bufsize);
+
+        cinfo.image_width = width;
+        cinfo.image_height = height;
+        cinfo.in_color_space = JCS_GRAYSCALE;
+        cinfo.input_components = 1;
+        jpeg_set_defaults(&mut cinfo);
+        jpeg_set_quality(&mut cinfo, quality, true as boolean);
+        jpeg_start_compress(&mut cinfo, true as boolean);
+        for row in pixels.chunks(width as usize) {
+            jpeg_write_scanlines(&mut cinfo, [row.as_ptr()].as_ptr(), 1);
+        }
+        jpeg_finish_compress(&mut cinfo);
+        jpeg_destroy_compress(&mut cinfo);
+
+        let res = std::slice::from_raw_parts(buf, bufsize as usize).to_vec();
+        libc::free(buf.cast());
+        res
+    }
+}
+
+fn decode_gray(data: &[u8]) -> (Vec<u8>, u32, u32) {
+    common::decode(data, |cinfo| cinfo.out_color_space = JCS_GRAYSCALE)
+}
+
+fn gradient(width: u32, height: u32, seed: u32) -> Vec<u8> {
+    (0..height).flat_map(|y| (0..width).map(move |x| ((x * 3 + y * 5 + seed) % 251) as u8)).collect()
+}
+
+#[test]
+fn drop_is_lossless() {
+    let base = encode_gray(&gradient(64, 48, 0), 64, 48, 85);
+    let insert = encode_gray(&gradient(24, 16, 100), 24, 16, 85);
+    let (base_px, ..) = decode_gray(&base);
+    let (insert_px, ..) = decode_gray(&insert);
+
+    let res = drop_into(&base, &insert, 16, 8).unwrap();
+    let (res_px, width, height) = decode_gray(&res);
+    assert_eq!((64, 48), (width, height));
+
+    for y in 0..48 {
+        for x in 0..64 {
+            let expected = if (16..40).contains(&x) && (8..24).contains(&y) {
+                insert_px[(y - 8) * 24 + (x - 16)]
+            } else {
+                base_px[y * 64 + x]
+            };
+            assert_eq!(expected, res_px[y * 64 + x], "{x},{y}");
+        }
+    }
+}
+
+#[test]
+fn drop_rejects_bad_input() {
+    let base = encode_gray(&gradient(64, 48, 0), 64, 48, 85);
+    let insert = encode_gray(&gradient(24, 16, 100), 24, 16, 85);
+    assert!(matches!(drop_into(&base, &insert, 4, 8), Err(Error::InvalidArgument(_))));
+    assert!(matches!(drop_into(&base, &insert, 48, 8), Err(Error::InvalidArgument(_))));
+
+    let other_quality = encode_gray(&gradient(24, 16, 100), 24, 16, 50);
+    assert!(matches!(drop_into(&base, &other_quality, 16, 8), Err(Error::Incompatible(_))));
+
+    assert!(matches!(drop_into(&base, b"not a jpeg", 0, 0), Err(Error::Libjpeg { code: JERR_NO_SOI, .. })));
+}
+
+#[test]
+fn align_rect() {
+    let r = Rect { x: 5, y: 17, width: 10, height: 3 };
+    assert_eq!(Rect { x: 0, y: 16, width: 16, height: 16 }, r.align_to_imcu(16, 16, 100, 100));
+    assert_eq!(Rect { x: 0, y: 16, width: 16, height: 4 }, r.align_to_imcu(16, 16, 100, 20));
+    assert_eq!(Rect { x: 96, y: 96, width: 4, height: 4 }, Rect { x: 99, y: 99, width: 50, height: 50 }.align_to_imcu(8, 8, 100, 100));
+    assert_eq!(0, Rect { x: 200, y: 0, width: 5, height: 5 }.align_to_imcu(8, 8, 100, 100).width);
+}
+
+#[test]
+fn wipe_keeps_rest_exact() {
+    let orig = encode_gray(&gradient(64, 48, 7), 64, 48, 90);
+    let (orig_px, ..) = decode_gray(&orig);
+    assert_eq!((8, 8), imcu_size(&orig).unwrap());
+
+    let (gray, covered) = wipe(&orig, &[Rect { x: 5, y: 9, width: 10, height: 3 }], WipeFill::Gray).unwrap();
+    assert_eq!(vec![Rect { x: 0, y: 8, width: 16, height: 8 }], covered);
+    let (gray_px, ..) = decode_gray(&gray);
+
+    let (blur, _) = wipe(&orig, &[Rect { x: 60, y: 40, width: 100, height: 100 }], WipeFill::Blur).unwrap();
+    let (blur_px, ..) = decode_gray(&blur);
+
+    for y in 0..48 {
+        for x in 0..64 {
+            let i = y * 64 + x;
+            if x < 16 && (8..16).contains(&y) {
+                assert_eq!(128, gray_px[i]);
+            } else {
+                assert_eq!(orig_px[i], gray_px[i]);
+            }
+            if x >= 56 && y >= 40 {
+                assert_eq!(blur_px[40 * 64 + 56], blur_px[i]);
+            } else {
+                assert_eq!(orig_px[i], blur_px[i]);
+            }
+        }
+    }
+}
+
+#[test]
+fn stitch_tiles() {
+    let (w, h) = (64, 48);
+    let full = gradient(w, h, 3);
+    let tile_at = |x0: u32, y0: u32, tw: u32, th: u32| {
+        let px: Vec<u8> = (y0..y0 + th).flat_map(|y| full[(y * w + x0) as usize..(y * w + x0 + tw) as usize].to_vec()).collect();
+        encode_gray(&px, tw, th, 80)
+    };
+    // widths and heights of the last column and row aren't multiples of 8
+    let tiles = [tile_at(0, 0, 40, 16), tile_at(40, 0, 24, 16), tile_at(0, 16, 40, 32), tile_at(40, 16, 24, 32)];
+    let refs: Vec<&[u8]> = tiles.iter().map(|t| &t[..]).collect();
+    let joined = stitch(&refs, 2).unwrap();
+    let (joined_px, jw, jh) = decode_gray(&joined);
+    assert_eq!((w, h), (jw, jh));
+    for (i, tile) in tiles.iter().enumerate() {
+        let (tile_px, tw, th) = decode_gray(tile);
+        let (x0, y0) = ([0, 40][i % 2], [0, 16][i / 2]);
+        for y in 0..th {
+            for x in 0..tw {
+                assert_eq!(tile_px[(y * tw + x) as usize], joined_px[((y0 + y) * w + x0 + x) as usize]);
+            }
+        }
+    }
+
+    let taller = tile_at(40, 0, 24, 24);
+    assert!(matches!(stitch(&[&tiles[0], &taller], 2), Err(Error::Incompatible(_))));
+    let other_quality = encode_gray(&gradient(24, 16, 0), 24, 16, 50);
+    assert!(matches!(stitch(&[&tiles[0], &other_quality], 2), Err(Error::Incompatible(_))));
+    assert!(matches!(stitch(&refs, 3), Err(Error::InvalidArgument(_))));
+}
+
+#[test]
+fn split_and_stitch_roundtrip() {
+    let orig = std::fs::read("tests/test.jpg").unwrap();
+    let (imcu_width, imcu_height) = imcu_size(&orig).unwrap();
+    let (tile_width, tile_height) = (imcu_width * 5, imcu_height * 3);
+    assert!(matches!(split(&orig, tile_width + 1, tile_height), Err(Error::InvalidArgument(_))));
+
+    let tiles = split(&orig, tile_width, tile_height).unwrap();
+    let (orig_px, width, height) = decode_gray(&orig);
+    let columns = ((width + tile_width - 1) / tile_width) as usize;
+    assert_eq!(columns * ((height + tile_height - 1) / tile_height) as usize, tiles.len());
+
+    for (rect, tile) in &tiles {
+        let (_, w, h) = decode_gray(tile);
+        assert_eq!((rect.width, rect.height), (w, h));
+        assert!(rect.x + rect.width <= width && rect.y + rect.height <= height);
+    }
+    let (last, _) = tiles.last().unwrap();
+    assert_eq!((width, height), (last.x + last.width, last.y + last.height));
+
+    let refs: Vec<&[u8]> = tiles.iter().map(|(_, t)| &t[..]).collect();
+    let (joined_px, ..) = decode_gray(&stitch(&refs, columns).unwrap());
+    assert!(orig_px == joined_px);
+}
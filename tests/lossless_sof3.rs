@@ -0,0 +1,14 @@
+//! MozJPEG is based on libjpeg-turbo 2.1, which has no lossless (predictive) mode, so SOF3 files are rejected.
+//! This should start failing when the library gains `jpeg_enable_lossless()`.
+#![cfg(feature = "safe_api")]
+
+use mozjpeg_sys::*;
+mod common;
+
+#[test]
+fn lossless_is_unsupported() {
+    // SOI, SOF3 for a 1x1 8-bit grayscale image
+    let sof3 = [0xFF, 0xD8, 0xFF, 0xC3, 0, 11, 8, 0, 1, 0, 1, 1, 1, 0x11, 0];
+    let res = common::try_decode(&sof3, &mut ErrorMgr::new(), |_| Ok(()));
+    assert!(matches!(res, Err(Error::Libjpeg { code: JERR_SOF_UNSUPPORTED, .. })), "{res:?}");
+}
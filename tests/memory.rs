@@ -0,0 +1,40 @@
+#![cfg(feature = "safe_api")]
+
+use mozjpeg_sys::*;
+mod common;
+
+fn decode(data: &[u8], limit: usize) -> Result<(), Error> {
+    common::try_decode(data, &mut ErrorMgr::new(), |cinfo| {
+        unsafe { set_memory_limit(&mut cinfo.common, limit) };
+        Ok(())
+    }).map(drop)
+}
+
+#[test]
+fn memory_limit() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    decode(&data, 10_000_000).unwrap();
+
+    // coefficients of the progressive image alone take over 1MB
+    #[cfg(not(feature = "backing_store"))]
+    match decode(&data, 1_000_000) {
+        Err(Error::OutOfMemory { requested, limit: 1_000_000 }) => assert!(requested > 100_000, "{requested}"),
+        res => panic!("{res:?}"),
+    }
+    assert!(matches!(decode(&data, 1000), Err(Error::OutOfMemory { limit: 1000, .. })));
+}
+
+#[test]
+fn memory_limit_per_instance() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    std::thread::scope(|s| {
+        let threads: Vec<_> = (0..4).map(|i| {
+            let data = &data;
+            s.spawn(move || if i % 2 == 0 { decode(data, 10_000_000) } else { decode(data, 1000) })
+        }).collect();
+        for (i, t) in threads.into_iter().enumerate() {
+            let res = t.join().unwrap();
+            assert_eq!(i % 2 == 0, res.is_ok(), "{res:?}");
+        }
+    });
+}
@@ -0,0 +1,33 @@
+//! Cross-check of the `portable_simd` kernels against the C code. Under qemu-user:
+//!
+//! ```sh
+//! CARGO_TARGET_RISCV64GC_UNKNOWN_LINUX_GNU_RUNNER=qemu-riscv64 RUSTFLAGS="-C target-feature=+v" \
+//!   cargo test --target riscv64gc-unknown-linux-gnu --no-default-features --features portable_simd --test portable_simd
+//! ```
+#![cfg(feature = "portable_simd")]
+
+use mozjpeg_sys::*;
+
+mod simd_kernels;
+
+#[test]
+fn kernels_match_c() {
+    if simd_kernels::reference_child() {
+        return;
+    }
+    let info = simd_info();
+    // Nothing else can take priority on targets without SIMD code in MozJPEG
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64", target_arch = "mips", target_arch = "powerpc", target_arch = "powerpc64")))]
+    assert_eq!(InstructionSet::Portable, info.instruction_set);
+    // Elsewhere the asm SIMD (and on x86-64 the intrinsics) take priority if they were built
+    if info.is_active("idct_islow") || cfg!(all(feature = "x86_intrinsics_simd", target_arch = "x86_64")) {
+        eprintln!("portable_simd isn't used, because {:?} is", info.instruction_set);
+        return;
+    }
+    assert_eq!(InstructionSet::Portable, info.instruction_set);
+    for name in ["rgb_ycc", "h2v1_downsample", "h2v2_downsample", "fdct_islow", "fdct_ifast", "quantize"] {
+        assert!(info.is_active(name), "{name}");
+    }
+    simd_kernels::run();
+    simd_kernels::compare_with_c("kernels_match_c");
+}
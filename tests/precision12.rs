@@ -0,0 +1,127 @@
+#![cfg(feature = "precision12")]
+
+use mozjpeg_sys::precision12 as jpeg12;
+use mozjpeg_sys::precision12::{J12SAMPLE, MAXJ12SAMPLE};
+use mozjpeg_sys::*;
+use std::mem;
+
+mod common;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 48;
+
+/// Smooth gradients that use most of the 12-bit range
+fn gradient(components: usize) -> Vec<J12SAMPLE> {
+    (0..HEIGHT).flat_map(|y| (0..WIDTH).flat_map(move |x| (0..components).map(move |c| {
+        (x * 2500 / WIDTH + y * 10 + c * 400) as J12SAMPLE
+    }))).collect()
+}
+
+fn encode12(pixels: &[J12SAMPLE], color_space: J_COLOR_SPACE, components: usize) -> Vec<u8> {
+    unsafe {
+        let mut err: jpeg_error_mgr = mem::zeroed();
+        let mut cinfo: jpeg_compress_struct = mem::zeroed();
+        cinfo.common.err = jpeg12::jpeg_std_error(&mut err);
+        jpeg12::jpeg_create_compress(&mut cinfo);
+        let mut buf = std::ptr::null_mut();
+        let mut bufsize = 0;
+        jpeg12::jpeg_mem_dest(&mut cinfo, &mut buf, &mut bufsize);
+
+        cinfo.image_width = WIDTH as _;
+        cinfo.image_height = HEIGHT as _;
+        cinfo.in_color_space = color_space;
+        cinfo.input_components = components as _;
+        jpeg12::jpeg_set_defaults(&mut cinfo);
+        assert_eq!(12, cinfo.data_precision);
+        jpeg12::jpeg_set_quality(&mut cinfo, 95, false as boolean);
+        jpeg12::jpeg_start_compress(&mut cinfo, true as boolean);
+
+        for row in pixels.chunks_exact(WIDTH * components) {
+            assert_eq!(1, jpeg12::jpeg_write_scanlines(&mut cinfo, [row.as_ptr()].as_ptr(), 1));
+        }
+        jpeg12::jpeg_finish_compress(&mut cinfo);
+        jpeg12::jpeg_destroy_compress(&mut cinfo);
+
+        let res = std::slice::from_raw_parts(buf, bufsize as usize).to_vec();
+        libc::free(buf.cast());
+        res
+    }
+}
+
+fn decode12(data: &[u8]) -> (Vec<J12SAMPLE>, usize) {
+    unsafe {
+        let mut err: jpeg_error_mgr = mem::zeroed();
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = jpeg12::jpeg_std_error(&mut err);
+        jpeg12::jpeg_create_decompress(&mut cinfo);
+        jpeg12::jpeg_mem_src(&mut cinfo, data.as_ptr(), data.len() as _);
+        jpeg12::jpeg_read_header(&mut cinfo, true as boolean);
+        assert_eq!((WIDTH, HEIGHT), (cinfo.image_width as usize, cinfo.image_height as usize));
+        jpeg12::jpeg_start_decompress(&mut cinfo);
+
+        let components = cinfo.output_components as usize;
+        let mut pixels = vec![0; WIDTH * HEIGHT * components];
+        for row in pixels.chunks_exact_mut(WIDTH * components) {
+            assert_eq!(1, jpeg12::jpeg_read_scanlines(&mut cinfo, [row.as_mut_ptr()].as_mut_ptr(), 1));
+        }
+        jpeg12::jpeg_finish_decompress(&mut cinfo);
+        jpeg12::jpeg_destroy_decompress(&mut cinfo);
+        (pixels, components)
+    }
+}
+
+fn assert_close(expected: &[J12SAMPLE], actual: &[J12SAMPLE]) {
+    assert_eq!(expected.len(), actual.len());
+    let max_diff = expected.iter().zip(actual).map(|(&a, &b)| a.abs_diff(b)).max().unwrap();
+    assert!(actual.iter().all(|&s| s <= MAXJ12SAMPLE));
+    // 8-bit precision would be off by at least 16
+    assert!(max_diff < 80, "{max_diff}");
+    let total_diff: u64 = expected.iter().zip(actual).map(|(&a, &b)| u64::from(a.abs_diff(b))).sum();
+    assert!(total_diff / (expected.len() as u64) < 8, "{total_diff}");
+}
+
+#[test]
+fn roundtrip_gray() {
+    let pixels = gradient(1);
+    let jpeg = encode12(&pixels, J_COLOR_SPACE::JCS_GRAYSCALE, 1);
+    // SOF2 (progressive) with 12 in the precision field
+    let sof = jpeg.windows(2).position(|w| w == [0xFF, 0xC2]).unwrap();
+    assert_eq!(12, jpeg[sof + 4]);
+    let (decoded, components) = decode12(&jpeg);
+    assert_eq!(1, components);
+    assert_close(&pixels, &decoded);
+}
+
+#[test]
+fn roundtrip_rgb() {
+    let pixels = gradient(3);
+    let jpeg = encode12(&pixels, J_COLOR_SPACE::JCS_RGB, 3);
+    let (decoded, components) = decode12(&jpeg);
+    assert_eq!(3, components);
+    assert_close(&pixels, &decoded);
+}
+
+/// Both builds are linked together, and each rejects the other's files
+#[test]
+#[cfg(feature = "safe_api")]
+fn wrong_precision() {
+    let jpeg = encode12(&gradient(1), J_COLOR_SPACE::JCS_GRAYSCALE, 1);
+    let eight_bit = std::fs::read("tests/test.jpg").unwrap();
+
+    let mut err = ErrorMgr::new();
+    let res = common::try_decode(&jpeg, &mut err, |_| Ok(()));
+    assert!(matches!(res, Err(Error::Libjpeg { code: JERR_BAD_PRECISION, .. })), "{res:?}");
+
+    unsafe {
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = err.as_iface();
+        jpeg12::jpeg_create_decompress(&mut cinfo);
+        let res = catch(|| {
+            jpeg12::jpeg_mem_src(&mut cinfo, eight_bit.as_ptr(), eight_bit.len() as _);
+            jpeg12::jpeg_read_header(&mut cinfo, true as boolean);
+            Ok(())
+        });
+        jpeg12::jpeg_destroy_decompress(&mut cinfo);
+        assert!(matches!(res, Err(Error::Libjpeg { code: JERR_BAD_PRECISION, .. })), "{res:?}");
+    }
+}
@@ -0,0 +1,61 @@
+#![cfg(all(feature = "prefix_symbols", not(target_arch = "wasm32")))]
+//! Checks symbol tables with `nm`, which has to be installed
+
+use mozjpeg_sys::*;
+use std::path::Path;
+use std::process::Command;
+
+/// Global symbols defined in the file
+fn defined_symbols(path: &Path) -> Vec<String> {
+    let out = Command::new("nm").args(["-g", "-P", "--defined-only"]).arg(path).output()
+        .unwrap_or_else(|err| panic!("can't run nm, which this test needs: {err}"));
+    assert!(out.status.success(), "nm can't read {}: {}", path.display(), String::from_utf8_lossy(&out.stderr));
+    let symbols = String::from_utf8_lossy(&out.stdout).lines()
+        // archive members are listed as `lib.a[file.o]:`
+        .filter(|line| !line.ends_with(':'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|name| if cfg!(target_vendor = "apple") { name.strip_prefix('_').unwrap_or(name) } else { name })
+        .map(String::from)
+        .collect();
+    symbols
+}
+
+#[test]
+fn static_libraries_are_prefixed() {
+    let mut found_library = false;
+    for entry in std::fs::read_dir(env!("OUT_DIR")).unwrap() {
+        let path = entry.unwrap().path();
+        if !matches!(path.extension().and_then(|e| e.to_str()), Some("a" | "lib")) {
+            continue;
+        }
+        let symbols = defined_symbols(&path);
+        // `__` names belong to the compiler (e.g. `__x86.get_pc_thunk.bx`)
+        let leaked: Vec<_> = symbols.iter().filter(|s| !s.starts_with("mozjpeg") && !s.starts_with("__")).collect();
+        assert!(leaked.is_empty(), "{}: {leaked:?}", path.display());
+        // the layout probe is in its own archive
+        found_library |= symbols.iter().any(|s| s == "mozjpeg_jpeg_CreateCompress");
+    }
+    assert!(found_library);
+}
+
+/// Rust's implementations of C functions (e.g. `rust_alloc`) are prefixed too
+#[test]
+fn executable_has_no_libjpeg_symbols() {
+    let symbols = defined_symbols(&std::env::current_exe().unwrap());
+    let libjpeg_prefixes = ["jpeg_", "jinit_", "jsimd_", "jcopy_", "jtransform_", "jdiv_round_up", "jround_up", "jzero_far", "tj"];
+    let leaked: Vec<_> = symbols.iter().filter(|s| libjpeg_prefixes.iter().any(|p| s.starts_with(p))).collect();
+    assert!(leaked.is_empty(), "{leaked:?}");
+    assert!(symbols.iter().any(|s| s == "mozjpeg_jpeg_CreateCompress"));
+}
+
+#[test]
+fn prefixed_library_works() {
+    unsafe {
+        let mut err = std::mem::zeroed();
+        let mut cinfo: jpeg_compress_struct = std::mem::zeroed();
+        cinfo.common.err = jpeg_std_error(&mut err);
+        jpeg_create_compress(&mut cinfo);
+        assert!(0 != jpeg_c_bool_param_supported(&cinfo, JBOOLEAN_TRELLIS_QUANT));
+        jpeg_destroy_compress(&mut cinfo);
+    }
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "safe_api")]
+
+use mozjpeg_sys::*;
+use std::cell::RefCell;
+use std::mem;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+/// Compresses a noisy image with mozjpeg's default (slowest) settings
+fn compress(cancel_at: f32) -> (Result<(), Error>, Vec<Progress>) {
+    let (width, height) = (256, 256);
+    let pixels: Vec<u8> = (0..width * height * 3).map(|i| (i * 7919 % 251) as u8).collect();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut err = ErrorMgr::new();
+    unsafe {
+        let mut cinfo: jpeg_compress_struct = mem::zeroed();
+        cinfo.common.err = err.as_iface();
+        jpeg_create_compress(&mut cinfo);
+        let seen2 = Rc::clone(&seen);
+        let _progress = set_progress(&mut cinfo.common, move |p| {
+            seen2.borrow_mut().push(p);
+            if p.fraction >= cancel_at { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        });
+        let mut buf = std::ptr::null_mut();
+        let mut bufsize = 0;
+        let res = catch(|| {
+            jpeg_mem_dest(&mut cinfo, &mut buf, &mut bufsize);
+            cinfo.image_width = width as _;
+            cinfo.image_height = height as _;
+            cinfo.in_color_space = JCS_RGB;
+            cinfo.input_components = 3;
+            jpeg_set_defaults(&mut cinfo);
+            jpeg_start_compress(&mut cinfo, true as boolean);
+            for row in pixels.chunks(width * 3) {
+                jpeg_write_scanlines(&mut cinfo, [row.as_ptr()].as_ptr(), 1);
+            }
+            jpeg_finish_compress(&mut cinfo);
+            Ok(())
+        });
+        jpeg_destroy_compress(&mut cinfo);
+        libc::free(buf.cast());
+        let seen = seen.borrow().clone();
+        (res, seen)
+    }
+}
+
+#[test]
+fn reports_progress() {
+    let (res, seen) = compress(2.);
+    res.unwrap();
+    assert!(seen.len() > 10);
+    assert!(seen.last().unwrap().total_passes > 1);
+    assert!(seen.windows(2).all(|w| w[0].fraction <= w[1].fraction));
+    assert!(seen.iter().all(|p| (0. ..=1.).contains(&p.fraction)));
+    assert!(seen.last().unwrap().fraction > 0.8, "{:?}", seen.last());
+}
+
+#[test]
+fn cancels() {
+    let (res, seen) = compress(0.3);
+    assert_eq!(Err(Error::Cancelled), res);
+    let last = seen.last().unwrap();
+    assert!(last.fraction >= 0.3 && last.fraction < 0.8, "{last:?}");
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "rust_alloc")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+mod common;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn uses_global_allocator() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    let before = ALLOCATED.load(Relaxed);
+    common::decode(&data, |_| {});
+    // the progressive image needs all its coefficients in memory
+    assert!(ALLOCATED.load(Relaxed) - before > 800 * 723 * 2);
+}
@@ -0,0 +1,58 @@
+use mozjpeg_sys::*;
+use std::mem;
+
+mod common;
+
+/// Decodes RGB pixels, scaled by `1/denom` in the DCT domain
+fn decode_scaled(data: &[u8], denom: c_uint) -> (Vec<u8>, JDIMENSION, JDIMENSION) {
+    common::decode(data, |cinfo| {
+        cinfo.out_color_space = J_COLOR_SPACE::JCS_RGB;
+        cinfo.scale_num = 1;
+        cinfo.scale_denom = denom;
+    })
+}
+
+fn encode(pixels: &[u8], width: JDIMENSION, height: JDIMENSION) -> Vec<u8> {
+    unsafe {
+        let mut err: jpeg_error_mgr = mem::zeroed();
+        let mut cinfo: jpeg_compress_struct = mem::zeroed();
+        cinfo.common.err = jpeg_std_error(&mut err);
+        jpeg_create_compress(&mut cinfo);
+        let mut buf = std::ptr::null_mut();
+        let mut bufsize = 0;
+        jpeg_mem_dest(&mut cinfo, &mut buf, &mut bufsize);
+
+        cinfo.image_width = width;
+        cinfo.image_height = height;
+        cinfo.in_color_space = J_COLOR_SPACE::JCS_RGB;
+        cinfo.input_components = 3;
+        jpeg_set_defaults(&mut cinfo);
+        jpeg_start_compress(&mut cinfo, true as boolean);
+        for row in pixels.chunks_exact(width as usize * 3) {
+            jpeg_write_scanlines(&mut cinfo, [row.as_ptr()].as_ptr(), 1);
+        }
+        jpeg_finish_compress(&mut cinfo);
+        jpeg_destroy_compress(&mut cinfo);
+
+        let res = std::slice::from_raw_parts(buf, bufsize as usize).to_vec();
+        libc::free(buf.cast());
+        res
+    }
+}
+
+/// The compressor can't scale, but thumbnails can be scaled by the decompressor
+#[test]
+fn thumbnail_from_scaled_decode() {
+    let original = std::fs::read("tests/test.jpg").unwrap();
+    let (_, width, height) = decode_scaled(&original, 1);
+
+    for denom in [2, 4] {
+        let (pixels, thumb_width, thumb_height) = decode_scaled(&original, denom);
+        assert_eq!((width + denom - 1) / denom, thumb_width);
+        assert_eq!((height + denom - 1) / denom, thumb_height);
+
+        let jpeg = encode(&pixels, thumb_width, thumb_height);
+        let (_, out_width, out_height) = decode_scaled(&jpeg, 1);
+        assert_eq!((thumb_width, thumb_height), (out_width, out_height));
+    }
+}
@@ -0,0 +1,35 @@
+use mozjpeg_sys::*;
+use std::collections::HashSet;
+
+#[test]
+fn simd_info_is_consistent() {
+    let info = simd_info();
+    let names: HashSet<_> = info.kernels.iter().map(|k| k.name).collect();
+    assert_eq!(names.len(), info.kernels.len());
+    for name in ["rgb_ycc", "null_convert", "fdct_islow", "idct_islow", "huff_encode_one_block"] {
+        assert!(names.contains(name), "{name}");
+    }
+    let any_active = info.kernels.iter().any(|k| k.active);
+    assert_eq!(any_active, info.instruction_set != InstructionSet::None, "{info:?}");
+    assert_eq!(info.is_active("rgb_ycc"), info.kernels.iter().any(|k| k.name == "rgb_ycc" && k.active));
+    assert!(!info.is_active("nonexistent"));
+}
+
+#[test]
+#[cfg(not(any(feature = "with_simd", feature = "wasm_simd", feature = "portable_simd", feature = "x86_intrinsics_simd")))]
+fn simd_info_without_simd() {
+    let info = simd_info();
+    assert_eq!(info.instruction_set, InstructionSet::None);
+    assert!(info.kernels.iter().all(|k| !k.active));
+}
+
+#[test]
+#[cfg(all(feature = "nasm_simd", target_arch = "x86_64"))]
+fn simd_info_x86_64() {
+    let info = simd_info();
+    // nasm may be missing, but if it isn't, every x86-64 CPU has SSE2
+    if info.instruction_set != InstructionSet::None {
+        assert!(matches!(info.instruction_set, InstructionSet::Sse2 | InstructionSet::Avx2), "{info:?}");
+        assert!(info.is_active("fdct_islow"));
+    }
+}
@@ -0,0 +1,294 @@
+//! Checks of the Rust SIMD kernels (`wasm_simd`, `portable_simd` and `x86_intrinsics_simd`) against the C code.
+//! Shared by the `wasm`, `portable_simd` and `x86_intrinsics_*` tests.
+//!
+//! The DCTs are compared with the C functions directly. The other C kernels aren't exported, so `compare_with_c()`
+//! compares whole images with ones encoded by a copy of the test process that uses `force_instruction_set(InstructionSet::None)`.
+//! That can't be done on wasm32, so there `run()` checks them only against the formulas of the C code.
+use mozjpeg_sys::*;
+use std::mem;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, process::Command};
+
+extern "C" {
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jsimd_rgb_ycc_convert")]
+    fn jsimd_rgb_ycc_convert(cinfo: &mut jpeg_compress_struct, input_buf: JSAMPARRAY, output_buf: JSAMPIMAGE, output_row: JDIMENSION, num_rows: c_int);
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jsimd_h2v1_downsample")]
+    fn jsimd_h2v1_downsample(cinfo: &mut jpeg_compress_struct, compptr: &mut jpeg_component_info, input_data: JSAMPARRAY, output_data: JSAMPARRAY);
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jsimd_h2v2_downsample")]
+    fn jsimd_h2v2_downsample(cinfo: &mut jpeg_compress_struct, compptr: &mut jpeg_component_info, input_data: JSAMPARRAY, output_data: JSAMPARRAY);
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jsimd_fdct_islow")]
+    fn jsimd_fdct_islow(data: *mut DCTELEM);
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jsimd_fdct_ifast")]
+    fn jsimd_fdct_ifast(data: *mut DCTELEM);
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jsimd_quantize")]
+    fn jsimd_quantize(coef_block: *mut JCOEF, divisors: *mut DCTELEM, workspace: *mut DCTELEM);
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jpeg_fdct_islow")]
+    fn jpeg_fdct_islow(data: *mut DCTELEM);
+    #[cfg_attr(feature = "prefix_symbols", link_name = "mozjpeg_jpeg_fdct_ifast")]
+    fn jpeg_fdct_ifast(data: *mut DCTELEM);
+}
+
+/// xorshift32
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+pub fn run() {
+    rgb_ycc();
+    downsample();
+    fdct();
+    quantize();
+}
+
+fn rgb_ycc() {
+    let mut rng = Rng(1);
+    let layouts = [
+        (JCS_RGB, [0, 1, 2], 3), (JCS_EXT_RGB, [0, 1, 2], 3), (JCS_EXT_BGR, [2, 1, 0], 3),
+        (JCS_EXT_RGBX, [0, 1, 2], 4), (JCS_EXT_BGRA, [2, 1, 0], 4), (JCS_EXT_XBGR, [3, 2, 1], 4), (JCS_EXT_ARGB, [1, 2, 3], 4),
+    ];
+    for (color_space, [r, g, b], pixel_size) in layouts {
+        for width in [1, 16, 37, 100] {
+            let input = rng.bytes(width * pixel_size);
+            let mut planes = vec![vec![0u8; width]; 3];
+            unsafe {
+                let mut cinfo: jpeg_compress_struct = mem::zeroed();
+                cinfo.image_width = width as _;
+                cinfo.in_color_space = color_space;
+                let rows: Vec<JSAMPROW> = planes.iter_mut().map(|p| p.as_mut_ptr().cast_const()).collect();
+                let image: Vec<JSAMPARRAY> = rows.iter().map(|r| r as JSAMPARRAY).collect();
+                jsimd_rgb_ycc_convert(&mut cinfo, &input.as_ptr(), image.as_ptr(), 0, 1);
+            }
+            // `rgb_ycc_convert()` from `jccolor.c`
+            for (col, pixel) in input.chunks(pixel_size).enumerate() {
+                let [r, g, b] = [pixel[r], pixel[g], pixel[b]].map(i32::from);
+                let expected = [
+                    (19595 * r + 38470 * g + 7471 * b + 32768) >> 16,
+                    (-11059 * r - 21709 * g + 32768 * b + (128 << 16) + 32767) >> 16,
+                    (32768 * r - 27439 * g - 5329 * b + (128 << 16) + 32767) >> 16,
+                ];
+                let actual = [0, 1, 2].map(|c| i32::from(planes[c][col]));
+                assert_eq!(expected, actual, "{color_space:?} {width} {col}");
+            }
+        }
+    }
+}
+
+fn downsample() {
+    let mut rng = Rng(2);
+    for image_width in [1, 15, 16, 37, 100] {
+        let width_in_blocks = (image_width + 15) / 16;
+        let output_cols = width_in_blocks * 8;
+        let mut input = [rng.bytes(output_cols * 2), rng.bytes(output_cols * 2)];
+        // `expand_right_edge` makes the padding a copy of the last pixel
+        let mut expanded = input.clone();
+        for row in &mut expanded {
+            let last = row[image_width - 1];
+            row[image_width..].fill(last);
+        }
+        let mut h2v1 = vec![0u8; output_cols];
+        let mut h2v2 = vec![0u8; output_cols];
+        unsafe {
+            let mut cinfo: jpeg_compress_struct = mem::zeroed();
+            cinfo.image_width = image_width as _;
+            let mut compptr: jpeg_component_info = mem::zeroed();
+            compptr.width_in_blocks = width_in_blocks as _;
+            compptr.v_samp_factor = 1;
+            let rows: Vec<JSAMPROW> = input.iter_mut().map(|r| r.as_mut_ptr().cast_const()).collect();
+
+            cinfo.max_v_samp_factor = 1;
+            jsimd_h2v1_downsample(&mut cinfo, &mut compptr, rows.as_ptr(), &h2v1.as_mut_ptr().cast_const());
+            cinfo.max_v_samp_factor = 2;
+            jsimd_h2v2_downsample(&mut cinfo, &mut compptr, rows.as_ptr(), &h2v2.as_mut_ptr().cast_const());
+        }
+        assert_eq!(expanded, input);
+        // `h2v1_downsample()` and `h2v2_downsample()` from `jcsample.c`
+        let [row0, row1] = expanded.map(|r| r.into_iter().map(u32::from).collect::<Vec<_>>());
+        for col in 0..output_cols {
+            let (a, b) = (row0[col * 2], row0[col * 2 + 1]);
+            let (c, d) = (row1[col * 2], row1[col * 2 + 1]);
+            assert_eq!((a + b + (col as u32 & 1)) >> 1, h2v1[col].into(), "{image_width} {col}");
+            assert_eq!((a + b + c + d + 1 + (col as u32 & 1)) >> 2, h2v2[col].into(), "{image_width} {col}");
+        }
+    }
+}
+
+fn fdct() {
+    let mut rng = Rng(3);
+    for i in 0..1000 {
+        let block: [DCTELEM; 64] = match i {
+            0 => [-128; 64],
+            1 => [127; 64],
+            2 => std::array::from_fn(|i| if (i / 8 + i % 8) % 2 == 0 { -128 } else { 127 }),
+            _ => std::array::from_fn(|_| (rng.next() as u8) as DCTELEM - 128),
+        };
+        let (mut expected, mut actual) = (block, block);
+        unsafe {
+            jpeg_fdct_islow(expected.as_mut_ptr());
+            jsimd_fdct_islow(actual.as_mut_ptr());
+        }
+        assert_eq!(expected, actual, "islow {block:?}");
+        let (mut expected, mut actual) = (block, block);
+        unsafe {
+            jpeg_fdct_ifast(expected.as_mut_ptr());
+            jsimd_fdct_ifast(actual.as_mut_ptr());
+        }
+        assert_eq!(expected, actual, "ifast {block:?}");
+    }
+}
+
+/// `compute_reciprocal()` from `jcdctmgr.c`, for divisors that the SIMD version is used for
+fn divisors(quantval: &[u16; 64]) -> [DCTELEM; 256] {
+    let mut dtbl = [0; 256];
+    for (i, &q) in quantval.iter().enumerate() {
+        let divisor = u32::from(q) << 3;
+        let b = 31 - divisor.leading_zeros();
+        let mut r = 16 + b;
+        let mut fq = (1u64 << r) / u64::from(divisor);
+        let fr = (1u64 << r) % u64::from(divisor);
+        let mut c = divisor / 2;
+        if fr == 0 {
+            fq >>= 1;
+            r -= 1;
+        } else if fr <= u64::from(divisor / 2) {
+            c += 1;
+        } else {
+            fq += 1;
+        }
+        assert!(r > 16);
+        dtbl[i] = fq as u16 as DCTELEM;
+        dtbl[i + 64] = c as u16 as DCTELEM;
+        dtbl[i + 128] = (1u32 << (32 - r)) as u16 as DCTELEM;
+        dtbl[i + 192] = (r - 16) as DCTELEM;
+    }
+    dtbl
+}
+
+fn quantize() {
+    let mut rng = Rng(4);
+    for _ in 0..1000 {
+        let quantval: [u16; 64] = std::array::from_fn(|_| 2 + (rng.next() % 254) as u16);
+        let mut divisors = divisors(&quantval);
+        let mut workspace: [DCTELEM; 64] = std::array::from_fn(|_| (rng.next() % 16384) as DCTELEM - 8192);
+        let mut actual = [0; 64];
+        unsafe {
+            jsimd_quantize(actual.as_mut_ptr(), divisors.as_mut_ptr(), workspace.as_mut_ptr());
+        }
+        // `quantize()` from `jcdctmgr.c`
+        for i in 0..64 {
+            let temp = workspace[i];
+            let (recip, corr, shift) = (u32::from(divisors[i] as u16), u32::from(divisors[i + 64] as u16), divisors[i + 192]);
+            let product = (u32::from(temp.unsigned_abs()) + corr) * recip;
+            let q = (product >> (shift + 16)) as DCTELEM;
+            let expected = if temp < 0 { -q } else { q };
+            assert_eq!(expected, actual[i], "{temp} / {}", quantval[i] << 3);
+        }
+    }
+}
+
+/// Set to the output path in the copy of the test process that encodes with the C code
+#[cfg(not(target_arch = "wasm32"))]
+const REFERENCE_ENV: &str = "MOZJPEG_SYS_TEST_C_REFERENCE";
+
+/// In the copy of the test process started by `compare_with_c()`, encodes the images with the C code and returns `true`.
+/// Must be called before anything else initializes SIMD.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn reference_child() -> bool {
+    let Some(path) = std::env::var_os(REFERENCE_ENV) else {
+        return false;
+    };
+    force_instruction_set(InstructionSet::None).unwrap();
+    let mut out = Vec::new();
+    for (_, jpeg) in encode_all() {
+        out.extend_from_slice(&(jpeg.len() as u32).to_le_bytes());
+        out.extend_from_slice(&jpeg);
+    }
+    assert!(!simd_info().is_active("rgb_ycc"));
+    fs::write(path, out).unwrap();
+    true
+}
+
+/// Encodes images with the current SIMD kernels, and compares them with the output of the C code.
+/// `test_name` is the test that calls `reference_child()`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compare_with_c(test_name: &str) {
+    let path = std::env::temp_dir().join(format!("mozjpeg-sys-{}-{test_name}.bin", std::process::id()));
+    let status = Command::new(std::env::current_exe().unwrap())
+        .args([test_name, "--exact", "--test-threads=1"])
+        .env(REFERENCE_ENV, &path)
+        .status().unwrap();
+    assert!(status.success());
+    let reference = fs::read(&path).unwrap();
+    let _ = fs::remove_file(&path);
+
+    let mut reference = &reference[..];
+    for (desc, jpeg) in encode_all() {
+        let (len, rest) = reference.split_at(4);
+        let (expected, rest) = rest.split_at(u32::from_le_bytes(len.try_into().unwrap()) as usize);
+        reference = rest;
+        assert!(expected == jpeg, "{desc}");
+    }
+    assert!(reference.is_empty());
+}
+
+/// Noise encoded with all the kernels, in various layouts, with a description of each
+#[cfg(not(target_arch = "wasm32"))]
+fn encode_all() -> Vec<(String, Vec<u8>)> {
+    let mut rng = Rng(5);
+    let mut out = Vec::new();
+    for (color_space, pixel_size) in [(JCS_RGB, 3), (JCS_EXT_BGR, 3), (JCS_EXT_RGBX, 4), (JCS_EXT_XBGR, 4), (JCS_EXT_ARGB, 4)] {
+        for (width, height) in [(37, 19), (100, 32)] {
+            let pixels = rng.bytes(width * height * pixel_size);
+            for samp in [(2, 2), (2, 1), (1, 1)] {
+                for dct_method in [J_DCT_METHOD::JDCT_ISLOW, J_DCT_METHOD::JDCT_IFAST] {
+                    for quality in [50, 100] {
+                        let jpeg = unsafe { encode(&pixels, width, color_space, pixel_size, samp, dct_method, quality) };
+                        out.push((format!("{color_space:?} {width}x{height} {samp:?} {dct_method:?} q{quality}"), jpeg));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn encode(pixels: &[u8], width: usize, color_space: J_COLOR_SPACE, pixel_size: usize, (h_samp, v_samp): (c_int, c_int), dct_method: J_DCT_METHOD, quality: c_int) -> Vec<u8> {
+    let mut err: jpeg_error_mgr = mem::zeroed();
+    let mut cinfo: jpeg_compress_struct = mem::zeroed();
+    cinfo.common.err = jpeg_std_error(&mut err);
+    jpeg_create_compress(&mut cinfo);
+    let mut buf = std::ptr::null_mut();
+    let mut bufsize = 0;
+    jpeg_mem_dest(&mut cinfo, &mut buf, &mut bufsize);
+    cinfo.image_width = width as _;
+    cinfo.image_height = (pixels.len() / (width * pixel_size)) as _;
+    cinfo.in_color_space = color_space;
+    cinfo.input_components = pixel_size as _;
+    // mozjpeg's trellis quantization doesn't use the `quantize` kernel
+    jpeg_c_set_int_param(&mut cinfo, JINT_COMPRESS_PROFILE, JCP_FASTEST as c_int);
+    jpeg_set_defaults(&mut cinfo);
+    jpeg_set_quality(&mut cinfo, quality, true as boolean);
+    cinfo.dct_method = dct_method;
+    (*cinfo.comp_info).h_samp_factor = h_samp;
+    (*cinfo.comp_info).v_samp_factor = v_samp;
+    jpeg_start_compress(&mut cinfo, true as boolean);
+    for row in pixels.chunks(width * pixel_size) {
+        jpeg_write_scanlines(&mut cinfo, [row.as_ptr()].as_ptr(), 1);
+    }
+    jpeg_finish_compress(&mut cinfo);
+    jpeg_destroy_compress(&mut cinfo);
+    let jpeg = std::slice::from_raw_parts(buf, bufsize as usize).to_vec();
+    libc::free(buf.cast());
+    jpeg
+}
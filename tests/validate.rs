@@ -0,0 +1,52 @@
+#![cfg(feature = "validate")]
+
+use mozjpeg_sys::*;
+
+#[test]
+fn valid_file() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    let report = validate(&data);
+    assert!(report.is_valid(), "{report:#?}");
+}
+
+#[test]
+fn truncated_file() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    let truncated = &data[..data.len() * 2 / 3];
+    let report = validate(truncated);
+    assert!(report.error.is_none());
+    assert!(report.has_warning(JWRN_JPEG_EOF), "{report:#?}");
+    let eof = report.warnings.iter().find(|w| w.code == JWRN_JPEG_EOF).unwrap();
+    // libjpeg may have buffered the last byte or two of a marker
+    assert!((truncated.len() - 2..=truncated.len()).contains(&eof.offset.unwrap()));
+    assert_eq!(MarkerProblem::MissingEoi, report.issues.last().unwrap().problem);
+}
+
+#[test]
+fn trailing_data() {
+    let mut data = std::fs::read("tests/test.jpg").unwrap();
+    let len = data.len();
+    data.extend_from_slice(b"garbage");
+    let report = validate(&data);
+    assert_eq!(vec![MarkerIssue { offset: len, problem: MarkerProblem::TrailingData(7) }], report.issues);
+}
+
+#[test]
+fn corrupt_segments() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+
+    let mut bad_length = data.clone();
+    bad_length[4..6].copy_from_slice(&2000u16.to_be_bytes());
+    assert!(!validate(&bad_length).is_valid());
+
+    let mut corrupt_scan = data.clone();
+    let mid = data.len() / 2;
+    corrupt_scan[mid..mid + 8].copy_from_slice(&[0xFF, 0x00, 0x12, 0xFF, 0xC9, 0x77, 0xFF, 0xD3]);
+    let report = validate(&corrupt_scan);
+    assert!(!report.is_valid());
+    assert!(!report.warnings.is_empty() || report.error.is_some(), "{report:#?}");
+
+    let report = validate(b"GIF89a");
+    assert_eq!(MarkerProblem::MissingSoi, report.issues[0].problem);
+    assert!(matches!(report.error, Some(Error::Libjpeg { code: JERR_NO_SOI, .. })));
+}
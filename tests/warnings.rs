@@ -0,0 +1,109 @@
+#![cfg(feature = "safe_api")]
+
+use mozjpeg_sys::*;
+use std::ffi::CStr;
+use std::mem;
+
+mod common;
+
+fn decode(data: &[u8], err: &mut ErrorMgr) -> Result<(), Error> {
+    common::try_decode(data, err, |_| Ok(())).map(drop)
+}
+
+/// test.jpg with junk bytes before the second marker
+fn with_extraneous_data() -> Vec<u8> {
+    let mut data = std::fs::read("tests/test.jpg").unwrap();
+    let app0_len = u16::from_be_bytes([data[4], data[5]]) as usize;
+    let pos = 4 + app0_len;
+    data.splice(pos..pos, [1, 2, 3]);
+    data
+}
+
+#[test]
+fn lenient_collects_warnings() {
+    let mut err = ErrorMgr::new();
+    decode(&with_extraneous_data(), &mut err).unwrap();
+    let warnings = err.take_warnings();
+    assert_eq!(1, warnings.len(), "{warnings:?}");
+    assert_eq!(JWRN_EXTRANEOUS_DATA, warnings[0].code);
+    assert_eq!(3, warnings[0].params[0]);
+    assert!(warnings[0].message.contains("3 extraneous bytes"));
+    assert_eq!(1, err.as_iface().num_warnings);
+}
+
+#[test]
+fn abort_on_selected_warnings() {
+    let data = std::fs::read("tests/test.jpg").unwrap();
+    let truncated = &data[..data.len() / 2];
+
+    let mut err = ErrorMgr::new();
+    err.abort_on(|w| w.code == JWRN_JPEG_EOF);
+    decode(&with_extraneous_data(), &mut err).unwrap();
+    let res = decode(truncated, &mut err);
+    assert!(matches!(res, Err(Error::Warning(Warning { code: JWRN_JPEG_EOF, .. }))), "{res:?}");
+    assert_eq!(JWRN_JPEG_EOF, err.warnings().last().unwrap().code);
+
+    let mut err = ErrorMgr::new();
+    err.strict();
+    assert!(matches!(decode(&with_extraneous_data(), &mut err), Err(Error::Warning(Warning { code: JWRN_EXTRANEOUS_DATA, .. }))));
+    decode(&data, &mut err).unwrap();
+}
+
+#[test]
+fn message_matches_format_message() {
+    unsafe {
+        let mut err = mem::zeroed();
+        jpeg_std_error(&mut err);
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = &mut err;
+
+        for (code, params) in [(JWRN_EXTRANEOUS_DATA, [3, 0xDB, 0]), (JTRC_SOF, [0xC2, 800, 723]), (JERR_BAD_PRECISION, [12, 0, 0]), (100_000, [0, 0, 0])] {
+            let err = &mut *cinfo.common.err;
+            err.msg_code = code;
+            let ints = &mut *err.msg_parm.i();
+            ints[..3].copy_from_slice(&params);
+            let mut buffer = [0u8; 80];
+            (err.format_message.unwrap())(&mut cinfo.common, &mut buffer);
+            let len = buffer.iter().position(|&c| c == 0).unwrap();
+            assert_eq!(std::str::from_utf8(&buffer[..len]).unwrap(), (*cinfo.common.err).message());
+        }
+    }
+}
+
+#[test]
+fn addon_messages() {
+    const JERR_CHUNK_MISSING: J_MESSAGE_CODE = 1000;
+    const JWRN_CHUNK_CRC: J_MESSAGE_CODE = 1001;
+    let messages: &'static [&'static CStr] = Box::leak(Box::new([
+        CStr::from_bytes_with_nul(b"Chunk %d is missing\0").unwrap(),
+        CStr::from_bytes_with_nul(b"Chunk %d has bad CRC %04x\0").unwrap(),
+    ]));
+
+    let mut err = ErrorMgr::new();
+    err.as_iface().set_addon_messages(JERR_CHUNK_MISSING, messages);
+    unsafe {
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = err.as_iface();
+        jpeg_create_decompress(&mut cinfo);
+        let res = catch(|| {
+            cinfo.common.warn(JWRN_CHUNK_CRC, &[3, 0xBEEF]);
+            cinfo.common.error_exit(JERR_CHUNK_MISSING, &[4]);
+        });
+        jpeg_destroy_decompress(&mut cinfo);
+        assert_eq!(Err::<(), _>(Error::Libjpeg { code: JERR_CHUNK_MISSING, message: "Chunk 4 is missing".into() }), res);
+    }
+    let warnings = err.take_warnings();
+    assert_eq!("Chunk 3 has bad CRC beef", warnings[0].message);
+    assert_eq!([3, 0xBEEF], warnings[0].params[..2]);
+
+    // C formats them too
+    let iface = err.as_iface();
+    iface.msg_code = JWRN_CHUNK_CRC;
+    let mut buffer = [0u8; 80];
+    let mut cinfo: jpeg_common_struct = unsafe { mem::zeroed() };
+    cinfo.err = iface;
+    unsafe { (iface.format_message.unwrap())(&mut cinfo, &mut buffer) };
+    let len = buffer.iter().position(|&c| c == 0).unwrap();
+    assert_eq!(iface.message().as_bytes(), &buffer[..len]);
+    assert_eq!("Chunk 4 has bad CRC beef", iface.message());
+}
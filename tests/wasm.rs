@@ -0,0 +1,96 @@
+//! Runs natively as a normal test, and on wasm32-unknown-unknown as `run_tests` export:
+//!
+//! ```sh
+//! cargo test --target wasm32-unknown-unknown --no-default-features --test wasm
+//! ```
+//!
+//! `.cargo/config.toml` sets `wasmtime` as the runner. There's no unwinding on this target,
+//! so errors go to the default `error_exit`, which traps.
+use mozjpeg_sys::*;
+use std::mem;
+
+// On wasm32-unknown-unknown this is the crate's Rust allocator
+extern "C" {
+    fn free(ptr: *mut c_void);
+}
+
+fn compress(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    unsafe {
+        let mut err: jpeg_error_mgr = mem::zeroed();
+        let mut cinfo: jpeg_compress_struct = mem::zeroed();
+        cinfo.common.err = jpeg_std_error(&mut err);
+        jpeg_create_compress(&mut cinfo);
+        let mut buf = std::ptr::null_mut();
+        let mut bufsize = 0;
+        jpeg_mem_dest(&mut cinfo, &mut buf, &mut bufsize);
+        cinfo.image_width = width as _;
+        cinfo.image_height = height as _;
+        cinfo.in_color_space = JCS_RGB;
+        cinfo.input_components = 3;
+        jpeg_set_defaults(&mut cinfo);
+        jpeg_set_quality(&mut cinfo, 90, true as boolean);
+        jpeg_start_compress(&mut cinfo, true as boolean);
+        for row in pixels.chunks(width * 3) {
+            jpeg_write_scanlines(&mut cinfo, [row.as_ptr()].as_ptr(), 1);
+        }
+        jpeg_finish_compress(&mut cinfo);
+        jpeg_destroy_compress(&mut cinfo);
+        let jpeg = std::slice::from_raw_parts(buf, bufsize as usize).to_vec();
+        free(buf.cast());
+        jpeg
+    }
+}
+
+fn decompress(jpeg: &[u8]) -> (usize, usize, Vec<u8>) {
+    unsafe {
+        let mut err: jpeg_error_mgr = mem::zeroed();
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = jpeg_std_error(&mut err);
+        jpeg_create_decompress(&mut cinfo);
+        jpeg_mem_src(&mut cinfo, jpeg.as_ptr(), jpeg.len() as _);
+        jpeg_read_header(&mut cinfo, true as boolean);
+        cinfo.out_color_space = JCS_RGB;
+        jpeg_start_decompress(&mut cinfo);
+        let (width, height) = (cinfo.output_width as usize, cinfo.output_height as usize);
+        let mut pixels = vec![0u8; width * height * 3];
+        for row in pixels.chunks_mut(width * 3) {
+            jpeg_read_scanlines(&mut cinfo, [row.as_mut_ptr()].as_mut_ptr(), 1);
+        }
+        jpeg_finish_decompress(&mut cinfo);
+        jpeg_destroy_decompress(&mut cinfo);
+        (width, height, pixels)
+    }
+}
+
+fn decodes_file() {
+    let (width, height, pixels) = decompress(include_bytes!("test.jpg"));
+    assert_eq!((800, 723), (width, height));
+    assert_eq!(width * height * 3, pixels.len());
+}
+
+fn roundtrip() {
+    let (width, height) = (64, 48);
+    let pixels: Vec<u8> = (0..width * height * 3).map(|i| (i % (width * 3)) as u8).collect();
+    let jpeg = compress(&pixels, width, height);
+    assert_eq!([0xFF, 0xD8], jpeg[..2]);
+    let (w, h, decoded) = decompress(&jpeg);
+    assert_eq!((width, height), (w, h));
+    let max_diff = pixels.iter().zip(&decoded).map(|(&a, &b)| a.abs_diff(b)).max().unwrap();
+    assert!(max_diff < 16, "{max_diff}");
+}
+
+#[no_mangle]
+pub extern "C" fn run_tests() {
+    decodes_file();
+    roundtrip();
+    #[cfg(all(feature = "wasm_simd", target_arch = "wasm32"))]
+    simd_kernels::run();
+}
+
+/// SIMD128 kernels must give exactly the same results as the C code
+#[cfg(all(feature = "wasm_simd", target_arch = "wasm32"))]
+mod simd_kernels;
+
+fn main() {
+    run_tests();
+}
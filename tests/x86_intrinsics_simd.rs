@@ -0,0 +1,25 @@
+//! Cross-check of the `x86_intrinsics_simd` kernels against the C code
+#![cfg(all(feature = "x86_intrinsics_simd", target_arch = "x86_64"))]
+
+use mozjpeg_sys::*;
+
+mod simd_kernels;
+
+#[test]
+fn kernels_match_c() {
+    if simd_kernels::reference_child() {
+        return;
+    }
+    let info = simd_info();
+    // `nasm_simd` takes priority when `nasm` was found. Otherwise the intrinsics must be used.
+    if cfg!(feature = "with_simd") && info.is_active("idct_islow") {
+        eprintln!("x86_intrinsics_simd isn't used, because nasm SIMD is");
+        return;
+    }
+    assert!(matches!(info.instruction_set, InstructionSet::Sse2 | InstructionSet::Avx2), "{:?}", info.instruction_set);
+    for name in ["rgb_ycc", "h2v1_downsample", "h2v2_downsample", "fdct_islow", "fdct_ifast", "quantize"] {
+        assert!(info.is_active(name), "{name}");
+    }
+    simd_kernels::run();
+    simd_kernels::compare_with_c("kernels_match_c");
+}
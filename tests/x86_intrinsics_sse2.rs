@@ -0,0 +1,22 @@
+//! Like `x86_intrinsics_simd.rs`, but with SSE2 forced on AVX2 CPUs. A separate binary, because the setting is process-wide.
+#![cfg(all(feature = "x86_intrinsics_simd", target_arch = "x86_64"))]
+
+use mozjpeg_sys::*;
+
+mod simd_kernels;
+
+#[test]
+fn sse2_kernels_match_c() {
+    if simd_kernels::reference_child() {
+        return;
+    }
+    force_instruction_set(InstructionSet::Sse2).unwrap();
+    let info = simd_info();
+    if cfg!(feature = "with_simd") && info.is_active("idct_islow") {
+        eprintln!("x86_intrinsics_simd isn't used, because nasm SIMD is");
+        return;
+    }
+    assert_eq!(InstructionSet::Sse2, info.instruction_set);
+    simd_kernels::run();
+    simd_kernels::compare_with_c("sse2_kernels_match_c");
+}
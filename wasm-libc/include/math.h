@@ -0,0 +1,13 @@
+/* Minimal <math.h> for wasm32-unknown-unknown. Provided by Rust's compiler-builtins. */
+#ifndef MOZJPEG_SYS_MATH_H
+#define MOZJPEG_SYS_MATH_H
+
+double sqrt(double x);
+double pow(double x, double y);
+double log(double x);
+double log2(double x);
+double ceil(double x);
+double floor(double x);
+double fabs(double x);
+
+#endif
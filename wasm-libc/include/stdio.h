@@ -0,0 +1,29 @@
+/* Minimal <stdio.h> for wasm32-unknown-unknown, which has no libc.
+ * There are no files: stdio functions fail, and messages are discarded. */
+#ifndef MOZJPEG_SYS_STDIO_H
+#define MOZJPEG_SYS_STDIO_H
+
+#include <stddef.h>
+#include <stdarg.h>
+
+typedef struct _mozjpeg_sys_file FILE;
+
+extern FILE *const stderr;
+extern FILE *const stdout;
+
+#define EOF (-1)
+#define SEEK_SET 0
+#define SEEK_CUR 1
+#define SEEK_END 2
+
+size_t fread(void *ptr, size_t size, size_t nmemb, FILE *stream);
+size_t fwrite(const void *ptr, size_t size, size_t nmemb, FILE *stream);
+int fflush(FILE *stream);
+int ferror(FILE *stream);
+int fseek(FILE *stream, long offset, int whence);
+int fprintf(FILE *stream, const char *format, ...);
+int printf(const char *format, ...);
+int snprintf(char *str, size_t size, const char *format, ...);
+int vsnprintf(char *str, size_t size, const char *format, va_list ap);
+
+#endif
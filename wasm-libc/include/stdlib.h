@@ -0,0 +1,20 @@
+/* Minimal <stdlib.h> for wasm32-unknown-unknown. Memory is allocated by Rust's global allocator. */
+#ifndef MOZJPEG_SYS_STDLIB_H
+#define MOZJPEG_SYS_STDLIB_H
+
+#include <stddef.h>
+
+#define EXIT_SUCCESS 0
+#define EXIT_FAILURE 1
+
+void *malloc(size_t size);
+void *calloc(size_t nmemb, size_t size);
+void *realloc(void *ptr, size_t size);
+void free(void *ptr);
+char *getenv(const char *name);
+void exit(int status) __attribute__((noreturn));
+void abort(void) __attribute__((noreturn));
+int abs(int x);
+long labs(long x);
+
+#endif
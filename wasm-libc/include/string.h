@@ -0,0 +1,14 @@
+/* Minimal <string.h> for wasm32-unknown-unknown. Provided by Rust's compiler-builtins. */
+#ifndef MOZJPEG_SYS_STRING_H
+#define MOZJPEG_SYS_STRING_H
+
+#include <stddef.h>
+
+void *memcpy(void *dest, const void *src, size_t n);
+void *memmove(void *dest, const void *src, size_t n);
+void *memset(void *s, int c, size_t n);
+int memcmp(const void *s1, const void *s2, size_t n);
+size_t strlen(const char *s);
+char *strncpy(char *dest, const char *src, size_t n);
+
+#endif
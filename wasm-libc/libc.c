@@ -0,0 +1,143 @@
+/*
+ * The few libc functions that libjpeg needs on wasm32-unknown-unknown, which has no libc.
+ * Memory allocation is in Rust (`src/jmemsys.rs`), and math and memory functions are
+ * provided by Rust's compiler-builtins.
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+struct _mozjpeg_sys_file { int unused; };
+static struct _mozjpeg_sys_file std_streams[2];
+FILE *const stderr = &std_streams[0];
+FILE *const stdout = &std_streams[1];
+
+size_t fread(void *ptr, size_t size, size_t nmemb, FILE *stream) { return 0; }
+size_t fwrite(const void *ptr, size_t size, size_t nmemb, FILE *stream) { return 0; }
+int fflush(FILE *stream) { return 0; }
+int ferror(FILE *stream) { return 1; }
+int fseek(FILE *stream, long offset, int whence) { return -1; }
+int fprintf(FILE *stream, const char *format, ...) { return 0; }
+int printf(const char *format, ...) { return 0; }
+
+char *getenv(const char *name) { return NULL; }
+void exit(int status) { __builtin_trap(); }
+void abort(void) { __builtin_trap(); }
+int abs(int x) { return x < 0 ? -x : x; }
+long labs(long x) { return x < 0 ? -x : x; }
+
+char *strncpy(char *dest, const char *src, size_t n)
+{
+  size_t i = 0;
+  for (; i < n && src[i]; i++)
+    dest[i] = src[i];
+  for (; i < n; i++)
+    dest[i] = 0;
+  return dest;
+}
+
+/* Appends to the output, keeping track of the length that would have been written */
+struct out {
+  char *str;
+  size_t size, len;
+};
+
+static void put(struct out *out, char c)
+{
+  if (out->len + 1 < out->size)
+    out->str[out->len] = c;
+  out->len++;
+}
+
+/* Subset of printf used by libjpeg's messages: %d %i %u %x %X %c %s %ld %lu %%, with 0 and - flags and width */
+int vsnprintf(char *str, size_t size, const char *format, va_list ap)
+{
+  struct out out = { str, size, 0 };
+  const char *p;
+
+  for (p = format; *p; p++) {
+    char buf[24], *s, *end;
+    int zero_pad = 0, left = 0, is_long = 0, width = 0, len, neg = 0;
+    unsigned long val, base = 10;
+    const char *digits = "0123456789abcdef";
+
+    if (*p != '%') {
+      put(&out, *p);
+      continue;
+    }
+    p++;
+    for (;; p++) {
+      if (*p == '0') zero_pad = 1;
+      else if (*p == '-') left = 1;
+      else break;
+    }
+    while (*p >= '0' && *p <= '9')
+      width = width * 10 + (*p++ - '0');
+    while (*p == 'l') {
+      is_long = 1;
+      p++;
+    }
+
+    end = s = buf + sizeof(buf);
+    switch (*p) {
+    case 'd': case 'i': {
+      long v = is_long ? va_arg(ap, long) : va_arg(ap, int);
+      neg = v < 0;
+      val = neg ? 0UL - (unsigned long)v : (unsigned long)v;
+      break;
+    }
+    case 'x': case 'X': case 'u':
+      val = is_long ? va_arg(ap, unsigned long) : va_arg(ap, unsigned int);
+      if (*p != 'u') base = 16;
+      if (*p == 'X') digits = "0123456789ABCDEF";
+      break;
+    case 'c':
+      *--s = (char)va_arg(ap, int);
+      break;
+    case 's':
+      s = va_arg(ap, char *);
+      if (!s) s = "(null)";
+      end = s + strlen(s);
+      break;
+    case '\0':
+      p--;
+      /* fall through */
+    default:
+      *--s = *p;
+      break;
+    }
+    if (*p == 'd' || *p == 'i' || *p == 'u' || *p == 'x' || *p == 'X') {
+      do {
+        *--s = digits[val % base];
+        val /= base;
+      } while (val);
+      if (neg && !zero_pad)
+        *--s = '-';
+    }
+
+    len = (int)(end - s) + (neg && zero_pad);
+    if (neg && zero_pad)
+      put(&out, '-');
+    if (!left)
+      for (; len < width; width--)
+        put(&out, zero_pad ? '0' : ' ');
+    for (; s < end; s++)
+      put(&out, *s);
+    for (; len < width; width--)
+      put(&out, ' ');
+  }
+  if (out.size)
+    out.str[out.len < out.size ? out.len : out.size - 1] = 0;
+  return (int)out.len;
+}
+
+int snprintf(char *str, size_t size, const char *format, ...)
+{
+  va_list ap;
+  int len;
+
+  va_start(ap, format);
+  len = vsnprintf(str, size, format, ap);
+  va_end(ap);
+  return len;
+}